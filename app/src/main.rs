@@ -4,16 +4,14 @@ use bevy::{
     prelude::*,
     window::{CursorGrabMode, PresentMode, PrimaryWindow},
 };
-use engine::{
-    bitboard::{self, Move},
-    parser, piece,
-};
+use engine::{attacks, bitboard, parser, piece};
 
 mod graphics;
 
 const BOARD_SIZE: f32 = 720.0;
 
 fn main() {
+    attacks::init();
     App::new()
         .insert_resource(ClearColor(Color::rgb(0.1, 0.1, 0.1)))
         .add_plugins((
@@ -90,20 +88,23 @@ fn drop_event_listener(
     for ev in evr_piece_drop.read() {
         if let Some(piece_entity) = grab_tool.dragged_piece_id {
             if let Ok((mut piece, mut transform)) = qy_piece.get_mut(piece_entity) {
-                if let Some(index) = ev.board_index {
-                    let mut board = qy_board.single_mut();
-                    // TODO: Check if move is legal
-                    board.bitboard.make_move(Move {
-                        origin: bitboard::Square { index: piece.index as u32 },
-                        target: bitboard::Square { index: index as u32 },
-                    });
+                let mut board = qy_board.single_mut();
+                let legal_move = ev.board_index.and_then(|index| {
+                    board.bitboard.get_legal_moves().into_iter().find(|mov| {
+                        mov.origin().index == piece.index as u32
+                            && mov.target().index == index as u32
+                    })
+                });
+                if let Some(mov) = legal_move {
+                    board.bitboard.make_move(mov);
+                    let index = mov.target().index as usize;
                     let coords = board.position_at(index);
                     transform.scale = Vec3::splat(1.0);
                     transform.translation = Vec3::new(coords.x, coords.y, 0.1);
                     piece.index = index;
                 } else {
                     // Go back to original square if the piece was not dropped in any
-                    // of the board's squares.
+                    // of the board's squares or the move is not legal.
                     *transform = grab_tool.dragged_piece_orig_transform;
                 }
             }
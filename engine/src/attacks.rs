@@ -0,0 +1,419 @@
+//! Precomputed attack tables.
+//!
+//! Knight, king and pawn attacks only depend on the square the piece stands on, so they are
+//! stored in plain tables. Sliding pieces also depend on the occupancy of the squares along
+//! their rays, which is mapped to an entry of a shared attack table by a perfect hash: the
+//! `PEXT` instruction when the crate is compiled with the `bmi2` target feature, or
+//! [magic bitboards](https://www.chessprogramming.org/Magic_Bitboards) otherwise.
+//!
+//! The tables are generated the first time they are needed. Call `init` at startup to avoid
+//! paying that cost in the middle of a search.
+
+use crate::bitboard::Square;
+use crate::init::{compute_squares_to_edge, DIRECTION_OFFSETS};
+use crate::piece::{Color, Piece, PieceType};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Indices into `DIRECTION_OFFSETS` for the orthogonal directions.
+const ORTHOGONAL: Range<usize> = 0..4;
+/// Indices into `DIRECTION_OFFSETS` for the diagonal directions.
+const DIAGONAL: Range<usize> = 4..8;
+
+/// `(file, rank)` offsets of the squares a knight can jump to.
+const KNIGHT_OFFSETS: &[(i32, i32)] = &[
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+
+/// `(file, rank)` offsets of the squares a king can step to.
+const KING_OFFSETS: &[(i32, i32)] = &[
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+/// Perfect hash of the relevant occupancy of a slider on one square.
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    /// Squares whose occupancy changes the attacks, that is, the rays without the edges.
+    mask: u64,
+    /// The magic number and the shift are unused when indexing with `PEXT`.
+    magic: u64,
+    #[cfg_attr(all(target_arch = "x86_64", target_feature = "bmi2"), allow(dead_code))]
+    shift: u32,
+    /// Start of the entries of this square in the shared attack table.
+    offset: usize,
+}
+
+impl Magic {
+    #[inline(always)]
+    fn index(&self, occupancy: u64) -> usize {
+        #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+        {
+            // SAFETY: The `bmi2` target feature is enabled at compile time.
+            let index = unsafe { std::arch::x86_64::_pext_u64(occupancy, self.mask) };
+            self.offset + index as usize
+        }
+        #[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2")))]
+        {
+            let hash = (occupancy & self.mask).wrapping_mul(self.magic);
+            self.offset + (hash >> self.shift) as usize
+        }
+    }
+}
+
+struct SliderTable {
+    magics: [Magic; 64],
+    attacks: Vec<u64>,
+}
+
+struct AttackTables {
+    knight: [u64; 64],
+    king: [u64; 64],
+    pawn: [[u64; 64]; 2],
+    rook: SliderTable,
+    bishop: SliderTable,
+    between: Box<[[u64; 64]; 64]>,
+    line: Box<[[u64; 64]; 64]>,
+}
+
+static TABLES: OnceLock<AttackTables> = OnceLock::new();
+
+#[inline(always)]
+fn tables() -> &'static AttackTables {
+    TABLES.get_or_init(AttackTables::new)
+}
+
+/// Generates the attack tables if they haven't been generated yet.
+pub fn init() {
+    tables();
+}
+
+/// Returns the squares attacked by a knight on the square with index `index`.
+#[inline]
+pub fn knight_attacks(index: u32) -> u64 {
+    tables().knight[index as usize]
+}
+
+/// Returns the squares attacked by a king on the square with index `index`.
+#[inline]
+pub fn king_attacks(index: u32) -> u64 {
+    tables().king[index as usize]
+}
+
+/// Returns the squares attacked by a pawn of `color` on the square with index `index`.
+#[inline]
+pub fn pawn_attacks(index: u32, color: Color) -> u64 {
+    tables().pawn[color as usize][index as usize]
+}
+
+/// Returns the squares attacked by a rook on the square with index `index`, given the
+/// `occupancy` of the board. The attacks include the first occupied square of each ray.
+#[inline]
+pub fn rook_attacks(index: u32, occupancy: u64) -> u64 {
+    let table = &tables().rook;
+    table.attacks[table.magics[index as usize].index(occupancy)]
+}
+
+/// Returns the squares attacked by a bishop on the square with index `index`, given the
+/// `occupancy` of the board. The attacks include the first occupied square of each ray.
+#[inline]
+pub fn bishop_attacks(index: u32, occupancy: u64) -> u64 {
+    let table = &tables().bishop;
+    table.attacks[table.magics[index as usize].index(occupancy)]
+}
+
+/// Returns the squares attacked by a queen on the square with index `index`, given the
+/// `occupancy` of the board. The attacks include the first occupied square of each ray.
+#[inline]
+pub fn queen_attacks(index: u32, occupancy: u64) -> u64 {
+    rook_attacks(index, occupancy) | bishop_attacks(index, occupancy)
+}
+
+/// Returns the squares attacked by `piece` on the square with index `index`, given the
+/// `occupancy` of the board.
+#[inline]
+pub fn piece_attacks(piece: Piece, index: u32, occupancy: u64) -> u64 {
+    match piece.piece_type() {
+        PieceType::Pawn => pawn_attacks(index, piece.color()),
+        PieceType::Knight => knight_attacks(index),
+        PieceType::Bishop => bishop_attacks(index, occupancy),
+        PieceType::Rook => rook_attacks(index, occupancy),
+        PieceType::Queen => queen_attacks(index, occupancy),
+        PieceType::King => king_attacks(index),
+    }
+}
+
+/// Returns the squares strictly between the squares with indices `a` and `b` if they share a
+/// rank, file or diagonal, and an empty bitboard otherwise.
+#[inline]
+pub fn between(a: u32, b: u32) -> u64 {
+    tables().between[a as usize][b as usize]
+}
+
+/// Returns every square of the rank, file or diagonal going through the squares with indices
+/// `a` and `b`, edge to edge, or an empty bitboard if they aren't aligned.
+#[inline]
+pub fn line(a: u32, b: u32) -> u64 {
+    tables().line[a as usize][b as usize]
+}
+
+impl AttackTables {
+    fn new() -> Self {
+        // A fixed seed makes the magic numbers, and so the tables, always the same.
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let squares_to_edge = compute_squares_to_edge();
+
+        let mut between = Box::new([[0; 64]; 64]);
+        let mut line = Box::new([[0; 64]; 64]);
+        for a in 0..64 {
+            for b in 0..64 {
+                let a_mask = Square { index: a }.bitmask();
+                let b_mask = Square { index: b }.bitmask();
+                for directions in [ORTHOGONAL, DIAGONAL] {
+                    let slide = |index, occupancy| {
+                        slider_targets(index, directions.clone(), occupancy, &squares_to_edge)
+                    };
+                    if a != b && slide(a, 0) & b_mask != 0 {
+                        between[a as usize][b as usize] = slide(a, b_mask) & slide(b, a_mask);
+                        line[a as usize][b as usize] = slide(a, 0) & slide(b, 0) | a_mask | b_mask;
+                    }
+                }
+            }
+        }
+
+        Self {
+            between,
+            line,
+            knight: std::array::from_fn(|index| step_targets(index as u32, KNIGHT_OFFSETS)),
+            king: std::array::from_fn(|index| step_targets(index as u32, KING_OFFSETS)),
+            pawn: [
+                std::array::from_fn(|index| step_targets(index as u32, &[(-1, 1), (1, 1)])),
+                std::array::from_fn(|index| step_targets(index as u32, &[(-1, -1), (1, -1)])),
+            ],
+            rook: SliderTable::new(ORTHOGONAL, &squares_to_edge, &mut rng),
+            bishop: SliderTable::new(DIAGONAL, &squares_to_edge, &mut rng),
+        }
+    }
+}
+
+impl SliderTable {
+    fn new(directions: Range<usize>, squares_to_edge: &[[usize; 8]; 64], rng: &mut StdRng) -> Self {
+        let mut magics = [Magic::default(); 64];
+        let mut attacks = Vec::new();
+
+        for index in 0..64 {
+            let slide =
+                |occupancy| slider_targets(index, directions.clone(), occupancy, squares_to_edge);
+            // The last square of each ray is attacked whether it's occupied or not.
+            let mut mask = 0;
+            for direction in directions.clone() {
+                let length = squares_to_edge[index as usize][direction].saturating_sub(1);
+                mask |= ray(index, direction, length);
+            }
+
+            // Enumerate every subset of the mask with the Carry-Rippler trick.
+            let mut occupancies = Vec::new();
+            let mut subset: u64 = 0;
+            loop {
+                occupancies.push((subset, slide(subset)));
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
+
+            let bits = mask.count_ones();
+            let mut magic = Magic {
+                mask,
+                magic: 0,
+                shift: 64 - bits,
+                offset: attacks.len(),
+            };
+            let mut entries = vec![0; 1 << bits];
+            if cfg!(all(target_arch = "x86_64", target_feature = "bmi2")) {
+                for &(occupancy, targets) in &occupancies {
+                    entries[magic.index(occupancy) - magic.offset] = targets;
+                }
+            } else {
+                magic.magic = find_magic(&magic, &occupancies, &mut entries, rng);
+            }
+
+            magics[index as usize] = magic;
+            attacks.extend(entries);
+        }
+
+        Self { magics, attacks }
+    }
+}
+
+/// Searches for a magic number that maps every occupancy to an entry holding its attacks,
+/// and fills `entries` with them.
+fn find_magic(
+    magic: &Magic,
+    occupancies: &[(u64, u64)],
+    entries: &mut [u64],
+    rng: &mut StdRng,
+) -> u64 {
+    // Entries written by a previous candidate are stale, tracking the attempt that wrote each
+    // entry avoids clearing the whole slice on every try.
+    let mut epoch = vec![0u32; entries.len()];
+    for attempt in 1.. {
+        let candidate = rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>();
+        if (magic.mask.wrapping_mul(candidate) >> 56).count_ones() < 6 {
+            continue;
+        }
+        let magic = Magic {
+            magic: candidate,
+            offset: 0,
+            ..*magic
+        };
+        let collides = occupancies.iter().any(|&(occupancy, targets)| {
+            let index = magic.index(occupancy);
+            if epoch[index] != attempt {
+                epoch[index] = attempt;
+                entries[index] = targets;
+                false
+            } else {
+                entries[index] != targets
+            }
+        });
+        if !collides {
+            return candidate;
+        }
+    }
+    unreachable!("A magic number is found for every square")
+}
+
+/// Returns the first `length` squares from `index` in `direction`, excluding `index`.
+fn ray(index: u32, direction: usize, length: usize) -> u64 {
+    (1..=length as i32)
+        .map(|step| index as i32 + step * DIRECTION_OFFSETS[direction])
+        .fold(0, |ray, target| {
+            ray | Square {
+                index: target as u32,
+            }
+            .bitmask()
+        })
+}
+
+/// Returns the bitboard of squares reached from `index` by each of the `(file, rank)`
+/// offsets, ignoring the ones that fall off the board.
+fn step_targets(index: u32, offsets: &[(i32, i32)]) -> u64 {
+    let file = (index % 8) as i32;
+    let rank = (index / 8) as i32;
+    offsets
+        .iter()
+        .map(|(df, dr)| (file + df, rank + dr))
+        .filter(|(f, r)| (0..8).contains(f) && (0..8).contains(r))
+        .fold(0, |targets, (f, r)| {
+            targets
+                | Square {
+                    index: (r * 8 + f) as u32,
+                }
+                .bitmask()
+        })
+}
+
+/// Returns the bitboard of squares reached by a slider on `index` moving along
+/// `directions`, walking the rays one square at a time. Each ray stops at (and includes)
+/// the first occupied square.
+fn slider_targets(
+    index: u32,
+    directions: Range<usize>,
+    occupancy: u64,
+    squares_to_edge: &[[usize; 8]; 64],
+) -> u64 {
+    let mut targets = 0;
+    for direction in directions {
+        let mut target = index as i32;
+        for _ in 0..squares_to_edge[index as usize][direction] {
+            target += DIRECTION_OFFSETS[direction];
+            let bitmask = Square {
+                index: target as u32,
+            }
+            .bitmask();
+            targets |= bitmask;
+            if occupancy & bitmask != 0 {
+                break;
+            }
+        }
+    }
+    targets
+}
+
+#[test]
+fn sliding_attacks_match_ray_walk() {
+    let squares_to_edge = compute_squares_to_edge();
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..2000 {
+        let occupancy = rng.gen::<u64>() & rng.gen::<u64>();
+        for index in 0..64 {
+            assert_eq!(
+                rook_attacks(index, occupancy),
+                slider_targets(index, ORTHOGONAL, occupancy, &squares_to_edge)
+            );
+            assert_eq!(
+                bishop_attacks(index, occupancy),
+                slider_targets(index, DIAGONAL, occupancy, &squares_to_edge)
+            );
+        }
+    }
+}
+
+#[test]
+fn between_and_line() {
+    let square = |text: &str| Square::from_notation(text).unwrap();
+    let squares = |texts: &[&str]| {
+        texts
+            .iter()
+            .fold(0, |bb, &text| bb | square(text).bitmask())
+    };
+    let (b2, e5, b5) = (square("b2").index, square("e5").index, square("b5").index);
+    assert_eq!(between(b2, e5), squares(&["c3", "d4"]));
+    assert_eq!(between(e5, b2), between(b2, e5));
+    assert_eq!(
+        line(b2, e5),
+        squares(&["a1", "b2", "c3", "d4", "e5", "f6", "g7", "h8"])
+    );
+    assert_eq!(between(b2, square("c3").index), 0);
+    assert_eq!(between(b2, b5), squares(&["b3", "b4"]));
+    assert_eq!(between(e5, square("f7").index), 0);
+    assert_eq!(line(e5, square("f7").index), 0);
+}
+
+#[test]
+fn leaper_attacks() {
+    let square = |text: &str| Square::from_notation(text).unwrap();
+    let squares = |texts: &[&str]| {
+        texts
+            .iter()
+            .fold(0, |bb, &text| bb | square(text).bitmask())
+    };
+    assert_eq!(knight_attacks(square("a1").index), squares(&["b3", "c2"]));
+    assert_eq!(
+        king_attacks(square("h8").index),
+        squares(&["g8", "g7", "h7"])
+    );
+    assert_eq!(
+        pawn_attacks(square("e4").index, Color::White),
+        squares(&["d5", "f5"])
+    );
+    assert_eq!(
+        pawn_attacks(square("a5").index, Color::Black),
+        squares(&["b4"])
+    );
+}
@@ -0,0 +1,402 @@
+//! Universal Chess Interface.
+//!
+//! Reads UCI commands from stdin and answers on stdout, so the engine can be played from
+//! any UCI graphical interface. The search runs on its own thread so that `stop`,
+//! `ponderhit` and `isready` are answered while it's in progress.
+
+use engine::game::Game;
+use engine::parser::{load_position_from_fen, STARTING_POSITION_FEN};
+use engine::search::{
+    mate_in, Limits, SearchOptions, SearchResult, Searcher, MAX_MULTI_PV, MAX_THREADS,
+};
+use engine::tablebase::Tablebases;
+use engine::time::{Clock, TimeManager};
+use engine::transposition::DEFAULT_SIZE_MB;
+use engine::{attacks, errors::Result};
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Depth each position is searched to by `bench` when none is given.
+const BENCH_DEPTH: u32 = 6;
+/// Positions searched by `bench`, to compare node counts and speed between versions.
+const BENCH_POSITIONS: &[&str] = &[
+    STARTING_POSITION_FEN,
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bqkb1r/pp3ppp/2n1pn2/2pp4/3P4/2PBPN2/PP3PPP/RNBQK2R w KQkq - 0 6",
+    "2r3k1/pp3ppp/4p3/3pP3/3P1P2/1P4P1/P5KP/2R5 b - - 0 28",
+    "6k1/5pp1/p6p/1p1P4/1P3P2/P5P1/6KP/8 w - - 0 40",
+    "r1b2rk1/2q1bppp/p2ppn2/1p6/3BP3/2NB4/PPPQ2PP/2KR3R w - - 2 13",
+];
+
+/// Names of the options switching search techniques on and off, see `search_option`.
+const SEARCH_OPTIONS: &[&str] = &[
+    "NullMove",
+    "LateMoveReductions",
+    "ReverseFutility",
+    "Futility",
+    "Razoring",
+    "CheckExtensions",
+    "AspirationWindows",
+];
+
+/// Returns the flag of `options` set by the UCI option `name`, in lowercase.
+fn search_option<'a>(options: &'a mut SearchOptions, name: &str) -> Option<&'a mut bool> {
+    Some(match name {
+        "nullmove" => &mut options.null_move,
+        "latemovereductions" => &mut options.late_move_reductions,
+        "reversefutility" => &mut options.reverse_futility,
+        "futility" => &mut options.futility,
+        "razoring" => &mut options.razoring,
+        "checkextensions" => &mut options.check_extensions,
+        "aspirationwindows" => &mut options.aspiration_windows,
+        _ => return None,
+    })
+}
+
+/// The parameters of a `go` command.
+#[derive(Clone, Copy, Debug, Default)]
+struct Go {
+    limits: Limits,
+    infinite: bool,
+    ponder: bool,
+}
+
+impl Go {
+    fn parse<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Self {
+        let mut go = Go::default();
+        let mut clock = Clock::default();
+        while let Some(token) = tokens.next() {
+            let mut value = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+            let millis = |value: Option<u64>| value.map(Duration::from_millis);
+            match token {
+                "depth" => go.limits.depth = value().map(|depth| depth as u32),
+                "nodes" => go.limits.nodes = value(),
+                "movetime" => clock.movetime = millis(value()),
+                "wtime" => clock.wtime = millis(value()),
+                "btime" => clock.btime = millis(value()),
+                "winc" => clock.winc = millis(value()).unwrap_or_default(),
+                "binc" => clock.binc = millis(value()).unwrap_or_default(),
+                "movestogo" => clock.movestogo = value().map(|moves| moves as u32),
+                "infinite" => go.infinite = true,
+                "ponder" => go.ponder = true,
+                _ => {}
+            }
+        }
+        if clock != Clock::default() {
+            go.limits.clock = Some(clock);
+        }
+        go
+    }
+}
+
+struct Uci {
+    game: Game,
+    /// The searcher, while no search is running.
+    searcher: Option<Searcher>,
+    /// The thread running the current search, which gives the searcher back when done.
+    search: Option<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+    /// Set while the search mustn't end on its own, for `go infinite` and `go ponder`.
+    waiting: Arc<AtomicBool>,
+    /// Incremented on every search, so a timer set for a search doesn't stop the next one.
+    search_id: Arc<AtomicU64>,
+    /// The time budget of the current search, for when the pondered move is played.
+    ponder_budget: Option<Duration>,
+}
+
+impl Uci {
+    fn new() -> Self {
+        let searcher = Searcher::new();
+        Self {
+            game: Game::default(),
+            stop: searcher.stop_flag(),
+            searcher: Some(searcher),
+            search: None,
+            waiting: Arc::new(AtomicBool::new(false)),
+            search_id: Arc::new(AtomicU64::new(0)),
+            ponder_budget: None,
+        }
+    }
+
+    /// Handles a command, returning `false` when the engine must quit.
+    fn command(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                println!(
+                    "id name {} {}",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                );
+                println!("id author the {} authors", env!("CARGO_PKG_NAME"));
+                println!(
+                    "option name Hash type spin default {} min 1 max 65536",
+                    DEFAULT_SIZE_MB
+                );
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!("option name Ponder type check default false");
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                println!("option name SyzygyPath type string default <empty>");
+                for name in SEARCH_OPTIONS {
+                    println!("option name {} type check default true", name);
+                }
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                self.searcher().clear();
+                self.game = Game::default();
+            }
+            Some("position") => {
+                if let Err(error) = self.position(tokens) {
+                    println!("info string {}", error);
+                }
+            }
+            Some("go") => self.go(Go::parse(tokens)),
+            Some("stop") => {
+                self.stop.store(true, Ordering::Relaxed);
+                self.wait();
+            }
+            Some("ponderhit") => self.ponderhit(),
+            Some("bench") => {
+                let depth = tokens.next().and_then(|depth| depth.parse().ok());
+                self.bench(depth.unwrap_or(BENCH_DEPTH));
+            }
+            Some("setoption") => self.set_option(line),
+            Some("quit") => {
+                self.stop.store(true, Ordering::Relaxed);
+                self.wait();
+                return false;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Waits for the search in progress to end and returns the searcher.
+    fn searcher(&mut self) -> &mut Searcher {
+        self.wait();
+        self.searcher.as_mut().expect("The searcher is back")
+    }
+
+    fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.searcher = Some(search.join().expect("The search thread doesn't panic"));
+        }
+    }
+
+    fn position<'a>(&mut self, mut tokens: impl Iterator<Item = &'a str>) -> Result<()> {
+        let fen = match tokens.next() {
+            Some("fen") => tokens
+                .by_ref()
+                .take_while(|&token| token != "moves")
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => {
+                // Skips "moves" after "startpos".
+                tokens.next();
+                STARTING_POSITION_FEN.to_string()
+            }
+        };
+        let mut game = Game::new(load_position_from_fen(&fen)?);
+        for text in tokens {
+            game.play(game.board().move_from_notation(text)?)?;
+        }
+        self.game = game;
+        Ok(())
+    }
+
+    fn go(&mut self, go: Go) {
+        self.wait();
+        let mut searcher = self.searcher.take().expect("The searcher is back");
+        let mut limits = go.limits;
+        self.ponder_budget = None;
+        // While pondering the clock isn't running yet, it's only started by `ponderhit`.
+        if go.ponder {
+            let color = self.game.board().color_to_move();
+            self.ponder_budget = limits
+                .clock
+                .take()
+                .and_then(|clock| TimeManager::new(&clock, color))
+                .map(|time_manager| time_manager.soft_limit());
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        self.waiting
+            .store(go.infinite || go.ponder, Ordering::Relaxed);
+        self.search_id.fetch_add(1, Ordering::Relaxed);
+
+        let game = self.game.clone();
+        let stop = Arc::clone(&self.stop);
+        let waiting = Arc::clone(&self.waiting);
+        self.search = Some(thread::spawn(move || {
+            let result = searcher.search_game_with(&game, &limits, |result| {
+                print_info(result);
+            });
+            // The best move can only be sent once the interface asks for it.
+            while waiting.load(Ordering::Relaxed) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            match result.best_move {
+                Some(mov) => match result.pv.get(1) {
+                    Some(ponder) => println!("bestmove {} ponder {}", mov, ponder),
+                    None => println!("bestmove {}", mov),
+                },
+                None => println!("bestmove 0000"),
+            }
+            searcher
+        }));
+    }
+
+    /// Switches the search of the move the opponent just played from pondering to a
+    /// regular search.
+    fn ponderhit(&mut self) {
+        self.waiting.store(false, Ordering::Relaxed);
+        let Some(budget) = self.ponder_budget.take() else {
+            return;
+        };
+        let stop = Arc::clone(&self.stop);
+        let search_id = Arc::clone(&self.search_id);
+        let id = search_id.load(Ordering::Relaxed);
+        thread::spawn(move || {
+            thread::sleep(budget);
+            if search_id.load(Ordering::Relaxed) == id {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+
+    /// Searches every position of `BENCH_POSITIONS` to `depth` from an empty table, prints
+    /// the total number of nodes and the speed and returns the number of nodes.
+    fn bench(&mut self, depth: u32) -> u64 {
+        // The last search may have been stopped, and what it learnt would change the nodes.
+        self.searcher().clear();
+        self.stop.store(false, Ordering::Relaxed);
+        let searcher = self.searcher();
+        let limits = Limits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        let (mut nodes, mut time) = (0, Duration::ZERO);
+        for fen in BENCH_POSITIONS {
+            searcher.tt().clear();
+            let board = load_position_from_fen(fen).expect("Bench positions are valid");
+            let result = searcher.search(&board, &limits);
+            nodes += result.nodes;
+            time += result.time;
+        }
+        let nps = nodes as u128 * 1000 / time.as_millis().max(1);
+        println!("{} nodes {} nps", nodes, nps);
+        nodes
+    }
+
+    fn set_option(&mut self, line: &str) {
+        // setoption name <name> [value <value>], where the name may contain spaces.
+        let Some((_, option)) = line.split_once("name") else {
+            return;
+        };
+        let (name, value) = match option.split_once("value") {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (option.trim(), ""),
+        };
+        let key = name.to_ascii_lowercase();
+        if let Some(flag) = search_option(self.searcher().options_mut(), &key) {
+            match value.parse() {
+                Ok(value) => *flag = value,
+                Err(_) => println!("info string invalid value {}", value),
+            }
+            return;
+        }
+        match (key.as_str(), value.parse::<usize>()) {
+            ("hash", Ok(size_mb)) => self.searcher().tt_mut().resize(size_mb.clamp(1, 65536)),
+            ("threads", Ok(threads)) => self.searcher().set_threads(threads),
+            ("multipv", Ok(lines)) => self.searcher().set_multi_pv(lines),
+            ("ponder", _) => {}
+            ("syzygypath", _) => self.set_syzygy_path(value),
+            _ => println!("info string unknown option {}", name),
+        }
+    }
+
+    /// Probes the tablebases found in `directory` from now on, or none if it's empty.
+    fn set_syzygy_path(&mut self, directory: &str) {
+        if directory.is_empty() || directory == "<empty>" {
+            self.searcher().set_tablebases(None);
+            return;
+        }
+        match Tablebases::open(directory) {
+            Ok(tablebases) => {
+                println!(
+                    "info string found {} tablebases of up to {} pieces",
+                    tablebases.len(),
+                    tablebases.max_pieces()
+                );
+                self.searcher().set_tablebases(Some(tablebases));
+            }
+            Err(error) => println!("info string {}", error),
+        }
+    }
+}
+
+/// Prints an `info` line for each line of `result`.
+fn print_info(result: &SearchResult) {
+    let millis = result.time.as_millis().max(1);
+    let nps = result.nodes as u128 * 1000 / millis;
+    for (i, line) in result.lines.iter().enumerate() {
+        let score = match mate_in(line.score) {
+            Some(mate) => format!("mate {}", mate),
+            None => format!("cp {}", line.score),
+        };
+        let pv = line
+            .moves
+            .iter()
+            .map(|mov| mov.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!(
+            "info depth {} multipv {} score {} nodes {} nps {} time {} pv {}",
+            result.depth,
+            i + 1,
+            score,
+            result.nodes,
+            nps,
+            result.time.as_millis(),
+            pv
+        );
+    }
+}
+
+fn main() {
+    attacks::init();
+    let mut uci = Uci::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !uci.command(&line) {
+            break;
+        }
+    }
+    uci.stop.store(true, Ordering::Relaxed);
+    uci.wait();
+}
+
+#[test]
+fn bench_after_stop() {
+    attacks::init();
+    let mut uci = Uci::new();
+    uci.command("go depth 1");
+    uci.command("stop");
+    assert_eq!(uci.bench(2), Uci::new().bench(2));
+}
@@ -5,11 +5,13 @@
 //!
 //! TODO: Expand this section.
 
-use crate::{bits, piece::*};
+use crate::errors::{Error, NotationError, NotationErrorKind, Result};
+use crate::eval::Score;
+use crate::{bits, eval, parser, piece::*, zobrist};
 use bitflags::bitflags;
+use std::fmt;
 use std::ops::Add;
-
-type Result<T> = std::result::Result<T, String>;
+use std::str::FromStr;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Board {
@@ -29,6 +31,13 @@ pub struct Board {
     // position metadata
     color_to_move: Color,
     castling_rights: CastleRights,
+    en_passant_square: Option<Square>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    /// Zobrist hash of the position, see the `zobrist` module.
+    hash: u64,
+    /// Material and piece-square score of the position, see the `eval` module.
+    psqt: Score,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -36,10 +45,36 @@ pub struct Square {
     pub index: u32,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct Move {
-    pub origin: Square,
-    pub target: Square,
+/// State that can't be recovered from a `Move` alone, saved by `Board::make_move` so the
+/// move can be taken back with `Board::undo_move`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Undo {
+    captured: Option<Piece>,
+    castling_rights: CastleRights,
+    en_passant_square: Option<Square>,
+    halfmove_clock: u32,
+    hash: u64,
+    psqt: Score,
+}
+
+/// A move packed in 16 bits.
+///
+/// The 6 least significant bits hold the index of the origin square, the next 6 bits hold
+/// the index of the target square and the 4 most significant bits encode the `MoveKind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move(u16);
+
+/// Describes what a `Move` does besides moving a piece from one square to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveKind {
+    Quiet,
+    DoublePawnPush,
+    KingCastle,
+    QueenCastle,
+    Capture,
+    EnPassant,
+    Promotion(PieceType),
+    PromotionCapture(PieceType),
 }
 
 bitflags! {
@@ -66,41 +101,48 @@ impl Square {
     /// The choosen board representation maps "a1" to the square with index 0 and "h8"
     /// to the one with index 63.
     pub fn from_notation(text: &str) -> Result<Self> {
-        let mut cs = text.chars();
-        if let Some(letter) = cs.next() {
-            let col: u32 = match letter {
-                'a' | 'A' => 0,
-                'b' | 'B' => 1,
-                'c' | 'C' => 2,
-                'd' | 'D' => 3,
-                'e' | 'E' => 4,
-                'f' | 'F' => 5,
-                'g' | 'G' => 6,
-                'h' | 'H' => 7,
-                _ => {
-                    return Err(format!(
-                        "Unknown file coordinate '{}'. Expected a, b, c, d, e, f, g or h",
-                        letter
-                    ))
-                }
-            };
-            if let Some(digit) = cs.next() {
-                let row: u32 = match digit.to_digit(10) {
-                    Some(x) if 1 <= x && x <= 8 => x - 1,
-                    _ => {
-                        return Err(format!(
-                            "Unknown rank coordinate '{}'. Expected 1, 2, 3, 4, 5, 6, 7 or 8",
-                            digit
-                        ))
-                    }
-                };
-                return Ok(Self {
-                    index: row * 8 + col,
-                });
-            }
+        if text.chars().count() != 2 {
+            return Err(notation_error(text, 0, NotationErrorKind::InvalidLength));
         }
+        Self::parse(text, 0)
+    }
 
-        Err(format!("Failed to parse '{}' as algebraic notation", text))
+    /// Parses the two characters of `text` found at byte `offset` as a square, reporting
+    /// errors relative to the whole `text`.
+    fn parse(text: &str, offset: usize) -> Result<Self> {
+        let mut cs = text[offset..].chars();
+        let (Some(letter), Some(digit)) = (cs.next(), cs.next()) else {
+            return Err(notation_error(
+                text,
+                offset,
+                NotationErrorKind::InvalidLength,
+            ));
+        };
+        let col: u32 = match letter {
+            'a' | 'A' => 0,
+            'b' | 'B' => 1,
+            'c' | 'C' => 2,
+            'd' | 'D' => 3,
+            'e' | 'E' => 4,
+            'f' | 'F' => 5,
+            'g' | 'G' => 6,
+            'h' | 'H' => 7,
+            _ => {
+                let kind = NotationErrorKind::UnknownFile(letter);
+                return Err(notation_error(text, offset, kind));
+            }
+        };
+        let row: u32 = match digit.to_digit(10) {
+            Some(x) if (1..=8).contains(&x) => x - 1,
+            _ => {
+                let kind = NotationErrorKind::UnknownRank(digit);
+                return Err(notation_error(text, offset + 1, kind));
+            }
+        };
+
+        Ok(Self {
+            index: row * 8 + col,
+        })
     }
 
     /// Returns the row index (rank) of the square.
@@ -112,35 +154,186 @@ impl Square {
     pub fn get_file(&self) -> u32 {
         self.index % 8
     }
+
+    /// Returns a bitboard where only the bit that represents this square is set.
+    pub const fn bitmask(&self) -> u64 {
+        (1 << 63) >> self.index
+    }
+}
+
+impl MoveKind {
+    fn to_bits(self) -> u16 {
+        match self {
+            MoveKind::Quiet => 0,
+            MoveKind::DoublePawnPush => 1,
+            MoveKind::KingCastle => 2,
+            MoveKind::QueenCastle => 3,
+            MoveKind::Capture => 4,
+            MoveKind::EnPassant => 5,
+            MoveKind::Promotion(piece_type) => 8 | promotion_to_bits(piece_type),
+            MoveKind::PromotionCapture(piece_type) => 12 | promotion_to_bits(piece_type),
+        }
+    }
+
+    fn from_bits(bits: u16) -> Self {
+        match bits {
+            0 => MoveKind::Quiet,
+            1 => MoveKind::DoublePawnPush,
+            2 => MoveKind::KingCastle,
+            3 => MoveKind::QueenCastle,
+            4 => MoveKind::Capture,
+            5 => MoveKind::EnPassant,
+            8..=11 => MoveKind::Promotion(promotion_from_bits(bits)),
+            12..=15 => MoveKind::PromotionCapture(promotion_from_bits(bits)),
+            _ => unreachable!("Unused move kind bits {:#x}", bits),
+        }
+    }
+}
+
+fn promotion_to_bits(piece_type: PieceType) -> u16 {
+    match piece_type {
+        PieceType::Knight => 0,
+        PieceType::Bishop => 1,
+        PieceType::Rook => 2,
+        PieceType::Queen => 3,
+        PieceType::Pawn | PieceType::King => panic!("Cannot promote to {:?}", piece_type),
+    }
+}
+
+fn promotion_from_bits(bits: u16) -> PieceType {
+    match bits & 0b11 {
+        0 => PieceType::Knight,
+        1 => PieceType::Bishop,
+        2 => PieceType::Rook,
+        _ => PieceType::Queen,
+    }
 }
 
 impl Move {
-    /// Constructs a `Move` from a string of text.
+    /// Constructs a `Move` of the given kind between two squares.
+    pub fn new(origin: Square, target: Square, kind: MoveKind) -> Self {
+        Self(origin.index as u16 | (target.index as u16) << 6 | kind.to_bits() << 12)
+    }
+
+    /// Constructs a `Move` from a string of text in UCI notation.
     ///
     /// The notation used expects 4 characters: the first two, represent the square from
     /// which the piece is moving, and the last two, represent the target square.
-    /// For example, "e2e4" is one of the most common opening moves for white.
+    /// For example, "e2e4" is one of the most common opening moves for white. A fifth
+    /// character may follow to indicate the piece a pawn promotes to, as in "e7e8q".
+    ///
+    /// Text alone can't tell captures, castling or en passant apart from other moves, so
+    /// the resulting move is either quiet or a promotion. Use `Board::move_from_notation`
+    /// to get the move with the right kind for a given position.
     pub fn from_notation(text: &str) -> Result<Self> {
-        if text.chars().count() != 4 {
-            return Err(format!("Failed to parse '{}', expected 4 characters", text));
+        let length = text.chars().count();
+        if !text.is_ascii() || !(4..=5).contains(&length) {
+            return Err(notation_error(text, 0, NotationErrorKind::InvalidLength));
         }
-        let origin = Square::from_notation(&text[0..2])?;
-        let target = Square::from_notation(&text[2..4])?;
+        let origin = Square::parse(text, 0)?;
+        let target = Square::parse(text, 2)?;
         if origin == target {
-            return Err(format!("Origin and target square cannot be the same"));
+            return Err(notation_error(text, 2, NotationErrorKind::SameSquare));
         }
+        let kind = match text[4..].chars().next() {
+            None => MoveKind::Quiet,
+            Some(symbol) => MoveKind::Promotion(match symbol.to_ascii_lowercase() {
+                'n' => PieceType::Knight,
+                'b' => PieceType::Bishop,
+                'r' => PieceType::Rook,
+                'q' => PieceType::Queen,
+                _ => {
+                    let kind = NotationErrorKind::UnknownPromotion(symbol);
+                    return Err(notation_error(text, 4, kind));
+                }
+            }),
+        };
 
-        Ok(Self { origin, target })
+        Ok(Self::new(origin, target, kind))
     }
 
-    /// Constructs a `Move` from two indices.
+    /// Constructs a quiet `Move` from two indices.
     ///
     /// This function does not check wether the indices are within bounds of the board,
     /// so you could end up with an impossible move.
     pub fn from_indices(origin: u32, target: u32) -> Self {
-        Self {
-            origin: Square { index: origin },
-            target: Square { index: target },
+        Self::new(
+            Square { index: origin },
+            Square { index: target },
+            MoveKind::Quiet,
+        )
+    }
+
+    /// Returns the 16 bits the move is packed in.
+    pub(crate) fn to_bits(self) -> u16 {
+        self.0
+    }
+
+    /// Constructs a `Move` from the bits returned by `to_bits`.
+    pub(crate) fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the square from which the piece is moving.
+    pub fn origin(self) -> Square {
+        Square {
+            index: (self.0 & 0x3f) as u32,
+        }
+    }
+
+    /// Returns the square the piece is moving to.
+    pub fn target(self) -> Square {
+        Square {
+            index: (self.0 >> 6 & 0x3f) as u32,
+        }
+    }
+
+    /// Returns the kind of the move.
+    pub fn kind(self) -> MoveKind {
+        MoveKind::from_bits(self.0 >> 12)
+    }
+
+    /// Returns the type of the piece a pawn promotes to, if the move is a promotion.
+    pub fn promotion(self) -> Option<PieceType> {
+        match self.kind() {
+            MoveKind::Promotion(piece_type) | MoveKind::PromotionCapture(piece_type) => {
+                Some(piece_type)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the move captures a piece, including en passant captures.
+    pub fn is_capture(self) -> bool {
+        matches!(
+            self.kind(),
+            MoveKind::Capture | MoveKind::EnPassant | MoveKind::PromotionCapture(_)
+        )
+    }
+
+    /// Returns `true` if the move is a kingside or queenside castle.
+    pub fn is_castling(self) -> bool {
+        matches!(self.kind(), MoveKind::KingCastle | MoveKind::QueenCastle)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file = (b'a' + self.get_file() as u8) as char;
+        write!(f, "{}{}", file, self.get_rank() + 1)
+    }
+}
+
+impl fmt::Display for Move {
+    /// Formats the move in UCI notation, the same one accepted by `Move::from_notation`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.origin(), self.target())?;
+        match self.promotion() {
+            Some(PieceType::Knight) => write!(f, "n"),
+            Some(PieceType::Bishop) => write!(f, "b"),
+            Some(PieceType::Rook) => write!(f, "r"),
+            Some(PieceType::Queen) => write!(f, "q"),
+            _ => Ok(()),
         }
     }
 }
@@ -166,6 +359,12 @@ impl Board {
             black_kings:   0x00_00_00_00_00_00_00_08,
             castling_rights: CastleRights::All,
             color_to_move: Color::White,
+            en_passant_square: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: zobrist::START_HASH,
+            // Both sides' pieces stand on mirrored squares.
+            psqt: Score::new(0, 0),
         }
     }
 
@@ -173,6 +372,9 @@ impl Board {
         array: &[Option<Piece>; 64],
         castling_rights: CastleRights,
         color_to_move: Color,
+        en_passant_square: Option<Square>,
+        halfmove_clock: u32,
+        fullmove_number: u32,
     ) -> Self {
         let mut white_pawns: u64 = 0;
         let mut white_rooks: u64 = 0;
@@ -230,7 +432,7 @@ impl Board {
             }
         }
 
-        Self {
+        let mut board = Self {
             white_pawns,
             white_rooks,
             white_knights,
@@ -245,15 +447,115 @@ impl Board {
             black_kings,
             castling_rights,
             color_to_move,
-        }
+            en_passant_square,
+            halfmove_clock,
+            fullmove_number,
+            hash: 0,
+            psqt: Score::default(),
+        };
+        board.hash = board.compute_hash();
+        board.psqt = board.compute_psqt();
+        board
     }
 
     /// Returns an array of `Option<Piece>` that represents squares of the board.
-    ///
-    /// TODO: Implement.
     pub fn piece_array(&self) -> [Option<Piece>; 64] {
-        let mut result = [None; 64];
-        result
+        std::array::from_fn(|index| self.at(index))
+    }
+
+    /// Returns the color of the pieces that have to make the next move.
+    pub fn color_to_move(&self) -> Color {
+        self.color_to_move
+    }
+
+    /// Returns the square a pawn can move to in order to capture en passant, if any.
+    pub fn en_passant_square(&self) -> Option<Square> {
+        self.en_passant_square
+    }
+
+    /// Returns the number of halfmoves since the last capture or pawn move, used by the
+    /// fifty-move rule.
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    /// Returns the number of the current full move. It starts at 1 and is incremented
+    /// after every move of the black pieces.
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    /// Returns the castling rights of both colors.
+    pub fn castling_rights(&self) -> CastleRights {
+        self.castling_rights
+    }
+
+    /// Returns the Zobrist hash of the position.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Returns the material and piece-square score of the position, from the point of
+    /// view of white.
+    pub fn psqt(&self) -> Score {
+        self.psqt
+    }
+
+    /// Returns the bitboard of the given piece type and color.
+    pub fn pieces(&self, piece: Piece) -> u64 {
+        match piece {
+            Piece::Pawn(Color::White) => self.white_pawns,
+            Piece::Knight(Color::White) => self.white_knights,
+            Piece::Bishop(Color::White) => self.white_bishops,
+            Piece::Rook(Color::White) => self.white_rooks,
+            Piece::Queen(Color::White) => self.white_queens,
+            Piece::King(Color::White) => self.white_kings,
+            Piece::Pawn(Color::Black) => self.black_pawns,
+            Piece::Knight(Color::Black) => self.black_knights,
+            Piece::Bishop(Color::Black) => self.black_bishops,
+            Piece::Rook(Color::Black) => self.black_rooks,
+            Piece::Queen(Color::Black) => self.black_queens,
+            Piece::King(Color::Black) => self.black_kings,
+        }
+    }
+
+    fn pieces_mut(&mut self, piece: Piece) -> &mut u64 {
+        match piece {
+            Piece::Pawn(Color::White) => &mut self.white_pawns,
+            Piece::Knight(Color::White) => &mut self.white_knights,
+            Piece::Bishop(Color::White) => &mut self.white_bishops,
+            Piece::Rook(Color::White) => &mut self.white_rooks,
+            Piece::Queen(Color::White) => &mut self.white_queens,
+            Piece::King(Color::White) => &mut self.white_kings,
+            Piece::Pawn(Color::Black) => &mut self.black_pawns,
+            Piece::Knight(Color::Black) => &mut self.black_knights,
+            Piece::Bishop(Color::Black) => &mut self.black_bishops,
+            Piece::Rook(Color::Black) => &mut self.black_rooks,
+            Piece::Queen(Color::Black) => &mut self.black_queens,
+            Piece::King(Color::Black) => &mut self.black_kings,
+        }
+    }
+
+    /// Returns the bitboard resulting from the union of all the piece bitboards of `color`.
+    pub fn color_occupancy(&self, color: Color) -> u64 {
+        match color {
+            Color::White => {
+                self.white_pawns
+                    | self.white_knights
+                    | self.white_bishops
+                    | self.white_rooks
+                    | self.white_queens
+                    | self.white_kings
+            }
+            Color::Black => {
+                self.black_pawns
+                    | self.black_knights
+                    | self.black_bishops
+                    | self.black_rooks
+                    | self.black_queens
+                    | self.black_kings
+            }
+        }
     }
 
     /// Returns the bitboard resulting from the union of all the piece bitboards.
@@ -335,16 +637,170 @@ impl Board {
         }
     }
 
-    pub fn get_legal_moves(&self, color_to_move: Color) -> Vec<Move> {
-        todo!()
+    /// Plays `mov` on the board and returns the information needed to take it back
+    /// with `undo_move`.
+    ///
+    /// The move is expected to be at least pseudo-legal and to have the right `MoveKind`,
+    /// playing anything else leaves the board in an unspecified state.
+    pub fn make_move(&mut self, mov: Move) -> Undo {
+        let (origin, target) = (mov.origin(), mov.target());
+        let piece = self
+            .at(origin.index as usize)
+            .expect("There is a piece on the origin square");
+        let color = piece.color();
+        let undo = Undo {
+            captured: self.at(target.index as usize),
+            castling_rights: self.castling_rights,
+            en_passant_square: self.en_passant_square,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+            psqt: self.psqt,
+        };
+        // The keys of the state that changes are toggled out now and back in at the end.
+        let mut hash = self.hash
+            ^ zobrist::castling_key(self.castling_rights)
+            ^ self.en_passant_hash()
+            ^ zobrist::side_key();
+
+        if let Some(captured) = undo.captured {
+            *self.pieces_mut(captured) &= !target.bitmask();
+            hash ^= zobrist::piece_key(captured, target);
+            self.psqt -= eval::piece_square(captured, target);
+        }
+        *self.pieces_mut(piece) &= !origin.bitmask();
+        let placed = match mov.promotion() {
+            Some(piece_type) => Piece::new(piece_type, color),
+            None => piece,
+        };
+        *self.pieces_mut(placed) |= target.bitmask();
+        hash ^= zobrist::piece_key(piece, origin) ^ zobrist::piece_key(placed, target);
+        self.psqt += eval::piece_square(placed, target) - eval::piece_square(piece, origin);
+
+        match mov.kind() {
+            MoveKind::EnPassant => {
+                let captured = en_passant_capture_square(target, color);
+                *self.pieces_mut(Piece::Pawn(!color)) &= !captured.bitmask();
+                hash ^= zobrist::piece_key(Piece::Pawn(!color), captured);
+                self.psqt -= eval::piece_square(Piece::Pawn(!color), captured);
+            }
+            MoveKind::KingCastle | MoveKind::QueenCastle => {
+                let (rook_origin, rook_target) = castling_rook_squares(mov);
+                let rooks = self.pieces_mut(Piece::Rook(color));
+                *rooks &= !rook_origin.bitmask();
+                *rooks |= rook_target.bitmask();
+                hash ^= zobrist::piece_key(Piece::Rook(color), rook_origin)
+                    ^ zobrist::piece_key(Piece::Rook(color), rook_target);
+                self.psqt += eval::piece_square(Piece::Rook(color), rook_target)
+                    - eval::piece_square(Piece::Rook(color), rook_origin);
+            }
+            _ => {}
+        }
+
+        self.en_passant_square = match mov.kind() {
+            MoveKind::DoublePawnPush => Some(Square {
+                index: (origin.index + target.index) / 2,
+            }),
+            _ => None,
+        };
+        self.halfmove_clock = match piece {
+            Piece::Pawn(_) => 0,
+            _ if mov.is_capture() => 0,
+            _ => self.halfmove_clock + 1,
+        };
+        if color == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.castling_rights
+            .remove(castling_rights_lost(origin) | castling_rights_lost(target));
+        self.color_to_move = !color;
+
+        self.hash = hash ^ zobrist::castling_key(self.castling_rights) ^ self.en_passant_hash();
+        debug_assert_eq!(self.hash, self.compute_hash());
+        debug_assert_eq!(self.psqt, self.compute_psqt());
+
+        undo
+    }
+
+    /// Takes back `mov`, which must be the last move played with `make_move`, restoring
+    /// the state saved in `undo`.
+    pub fn undo_move(&mut self, mov: Move, undo: Undo) {
+        let (origin, target) = (mov.origin(), mov.target());
+        let placed = self
+            .at(target.index as usize)
+            .expect("There is a piece on the target square");
+        let color = placed.color();
+        let piece = match mov.promotion() {
+            Some(_) => Piece::Pawn(color),
+            None => placed,
+        };
+
+        *self.pieces_mut(placed) &= !target.bitmask();
+        *self.pieces_mut(piece) |= origin.bitmask();
+        if let Some(captured) = undo.captured {
+            *self.pieces_mut(captured) |= target.bitmask();
+        }
+
+        match mov.kind() {
+            MoveKind::EnPassant => {
+                let captured = en_passant_capture_square(target, color);
+                *self.pieces_mut(Piece::Pawn(!color)) |= captured.bitmask();
+            }
+            MoveKind::KingCastle | MoveKind::QueenCastle => {
+                let (rook_origin, rook_target) = castling_rook_squares(mov);
+                let rooks = self.pieces_mut(Piece::Rook(color));
+                *rooks &= !rook_target.bitmask();
+                *rooks |= rook_origin.bitmask();
+            }
+            _ => {}
+        }
+
+        self.castling_rights = undo.castling_rights;
+        self.en_passant_square = undo.en_passant_square;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+        self.psqt = undo.psqt;
+        if color == Color::Black {
+            self.fullmove_number -= 1;
+        }
+        self.color_to_move = color;
     }
 
-    pub fn make_move(&mut self, mov: Move) {
-        todo!()
+    /// Passes the turn to the other side, for null move pruning, and returns the information
+    /// needed to take it back with `undo_null_move`.
+    ///
+    /// The halfmove clock is reset, so that no position before the null move counts as a
+    /// repetition of one after it. The side to move must not be in check.
+    pub fn make_null_move(&mut self) -> Undo {
+        let undo = Undo {
+            captured: None,
+            castling_rights: self.castling_rights,
+            en_passant_square: self.en_passant_square,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+            psqt: self.psqt,
+        };
+        self.hash ^= self.en_passant_hash() ^ zobrist::side_key();
+        self.en_passant_square = None;
+        self.halfmove_clock = 0;
+        if self.color_to_move == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.color_to_move = !self.color_to_move;
+        debug_assert_eq!(self.hash, self.compute_hash());
+
+        undo
     }
 
-    pub fn undo_move(&mut self, mov: Move) {
-        todo!()
+    /// Takes back the null move played with `make_null_move`, restoring the state saved in
+    /// `undo`.
+    pub fn undo_null_move(&mut self, undo: Undo) {
+        self.color_to_move = !self.color_to_move;
+        if self.color_to_move == Color::Black {
+            self.fullmove_number -= 1;
+        }
+        self.en_passant_square = undo.en_passant_square;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
     }
 
     /// Returns `true` if the `color` pieces have the right to castle kingside.
@@ -364,6 +820,72 @@ impl Board {
     }
 }
 
+impl fmt::Display for Board {
+    /// Formats the board as a FEN string.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", parser::fen_string(self))
+    }
+}
+
+impl FromStr for Board {
+    type Err = Error;
+
+    /// Parses a board from a FEN string.
+    fn from_str(s: &str) -> Result<Self> {
+        parser::load_position_from_fen(s)
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn notation_error(text: &str, offset: usize, kind: NotationErrorKind) -> Error {
+    Error::Notation(NotationError {
+        text: text.to_string(),
+        offset,
+        kind,
+    })
+}
+
+/// Returns the square of the pawn captured by a pawn of `color` moving en passant to `target`.
+fn en_passant_capture_square(target: Square, color: Color) -> Square {
+    match color {
+        Color::White => Square {
+            index: target.index - 8,
+        },
+        Color::Black => Square {
+            index: target.index + 8,
+        },
+    }
+}
+
+/// Returns the origin and target squares of the rook that moves along with the king when
+/// castling.
+fn castling_rook_squares(mov: Move) -> (Square, Square) {
+    let king = mov.origin().index;
+    if mov.kind() == MoveKind::KingCastle {
+        (Square { index: king + 3 }, Square { index: king + 1 })
+    } else {
+        (Square { index: king - 4 }, Square { index: king - 1 })
+    }
+}
+
+/// Returns the castling rights that are lost when a piece moves from or to `square`.
+fn castling_rights_lost(square: Square) -> CastleRights {
+    match square.index {
+        0 => CastleRights::WhiteQS,
+        4 => CastleRights::WhiteKS | CastleRights::WhiteQS,
+        7 => CastleRights::WhiteKS,
+        56 => CastleRights::BlackQS,
+        60 => CastleRights::BlackKS | CastleRights::BlackQS,
+        63 => CastleRights::BlackKS,
+        _ => CastleRights::None,
+    }
+}
+
 impl Add<(i32, i32)> for Square {
     type Output = Self;
 
@@ -385,6 +907,7 @@ fn square_struct() {
     assert!(Square::from_notation("a1").is_ok());
     assert!(Square::from_notation("h8").is_ok());
     assert!(Square::from_notation("u9").is_err());
+    assert!(Square::from_notation("e44").is_err());
 }
 
 #[test]
@@ -393,4 +916,96 @@ fn move_struct() {
     assert!(Move::from_notation("a1a1").is_err());
     assert!(Move::from_notation("a1h").is_err());
     assert!(Move::from_notation("ah8").is_err());
+    assert!(matches!(
+        Move::from_notation("e7e9q"),
+        Err(Error::Notation(NotationError {
+            offset: 3,
+            kind: NotationErrorKind::UnknownRank('9'),
+            ..
+        }))
+    ));
+    assert!(matches!(
+        Move::from_notation("e7e8k"),
+        Err(Error::Notation(NotationError { offset: 4, .. }))
+    ));
+    let mov = Move::from_notation("e7e8n").unwrap();
+    assert_eq!(mov.origin(), Square::from_notation("e7").unwrap());
+    assert_eq!(mov.target(), Square::from_notation("e8").unwrap());
+    assert_eq!(mov.promotion(), Some(PieceType::Knight));
+    assert_eq!(mov.to_string(), "e7e8n");
+    let mov = Move::new(
+        mov.origin(),
+        mov.target(),
+        MoveKind::PromotionCapture(PieceType::Queen),
+    );
+    assert!(mov.is_capture());
+    assert_eq!(mov.promotion(), Some(PieceType::Queen));
+}
+
+#[test]
+fn make_undo_move() {
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let mut board = crate::parser::load_position_from_fen(fen).unwrap();
+    let original = board.clone();
+    for mov in original.get_legal_moves() {
+        let undo = board.make_move(mov);
+        assert!(board.is_valid());
+        for reply in board.clone().get_legal_moves() {
+            let undo = board.make_move(reply);
+            board.undo_move(reply, undo);
+        }
+        board.undo_move(mov, undo);
+        assert_eq!(board, original);
+    }
+}
+
+#[test]
+fn make_move_special_moves() {
+    use crate::parser::load_position_from_fen;
+
+    let mut board = load_position_from_fen("r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1").unwrap();
+    board.make_move(board.move_from_notation("d4e3").unwrap());
+    assert_eq!(board.at(28), None);
+    assert_eq!(board.at(20), Some(Piece::Pawn(Color::Black)));
+    board.make_move(board.move_from_notation("e1c1").unwrap());
+    assert_eq!(board.at(3), Some(Piece::Rook(Color::White)));
+    assert!(!board.can_castle_kingside(Color::White));
+    board.make_move(board.move_from_notation("e3e2").unwrap());
+    board.make_move(board.move_from_notation("h1h2").unwrap());
+    board.make_move(board.move_from_notation("e2d1n").unwrap());
+    assert_eq!(board.at(3), Some(Piece::Knight(Color::Black)));
+}
+
+#[test]
+fn null_move() {
+    let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3";
+    let mut board = crate::parser::load_position_from_fen(fen).unwrap();
+    let original = board.clone();
+    let undo = board.make_null_move();
+    assert_eq!(board.color_to_move(), Color::White);
+    assert_eq!(board.en_passant_square(), None);
+    assert_eq!(board.halfmove_clock(), 0);
+    assert_eq!(board.fullmove_number(), 4);
+    board.undo_null_move(undo);
+    assert_eq!(board, original);
+}
+
+#[test]
+fn move_counters() {
+    let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+    let mut board = crate::parser::load_position_from_fen(fen).unwrap();
+    assert_eq!(board.halfmove_clock(), 1);
+    assert_eq!(board.fullmove_number(), 2);
+    let knight_move = board.move_from_notation("b8c6").unwrap();
+    let undo = board.make_move(knight_move);
+    assert_eq!(board.halfmove_clock(), 2);
+    assert_eq!(board.fullmove_number(), 3);
+    let pawn_move = board.move_from_notation("d2d4").unwrap();
+    let pawn_undo = board.make_move(pawn_move);
+    assert_eq!(board.halfmove_clock(), 0);
+    assert_eq!(board.en_passant_square(), Square::from_notation("d3").ok());
+    board.undo_move(pawn_move, pawn_undo);
+    board.undo_move(knight_move, undo);
+    assert_eq!(board.halfmove_clock(), 1);
+    assert_eq!(board.fullmove_number(), 2);
 }
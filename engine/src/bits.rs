@@ -9,7 +9,7 @@ pub fn count_ones(value: u64) -> u32 {
 /// Returns `true` if at most one bit is set to one.
 #[inline(always)]
 pub fn at_most_one(value: u64) -> bool {
-    value & (value - 1) == 0
+    value & value.wrapping_sub(1) == 0
 }
 
 /// Returns `true` if exactly one bit is set to one and the rest are zeroes.
@@ -23,3 +23,14 @@ pub fn only_one(value: u64) -> bool {
 pub fn more_than_one(value: u64) -> bool {
     !at_most_one(value)
 }
+
+/// Clears the most significant bit set to one and returns its position, counting from
+/// the most significant bit.
+///
+/// The value must not be zero.
+#[inline(always)]
+pub fn pop_msb(value: &mut u64) -> u32 {
+    let index = value.leading_zeros();
+    *value &= !((1 << 63) >> index);
+    index
+}
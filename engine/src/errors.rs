@@ -1,6 +1,223 @@
 //! Custom error types for this crate.
 
-pub enum Engine {
-    IoError(String),
-    ParseError(String),
+use crate::bitboard::Move;
+use std::{fmt, io};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type of this crate.
+#[derive(Debug)]
+pub enum Error {
+    /// Square or move notation that couldn't be parsed.
+    Notation(NotationError),
+    /// FEN record that couldn't be loaded.
+    Fen(FenError),
+    /// Move that is not legal in the position it was meant to be played.
+    IllegalMove(Move),
+    /// Board whose internal representation is not in a valid state.
+    InvalidBoard,
+    /// Opening book that is truncated, or with entries not sorted by key.
+    InvalidBook,
+    /// Tablebase file that is truncated, corrupted or doesn't match its name.
+    InvalidTablebase,
+    /// Failure reading or writing a file.
+    Io(io::Error),
+}
+
+/// What went wrong when parsing square or move notation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotationErrorKind {
+    /// The text doesn't have the expected number of characters.
+    InvalidLength,
+    /// The character is not a file coordinate.
+    UnknownFile(char),
+    /// The character is not a rank coordinate.
+    UnknownRank(char),
+    /// The character is not a piece a pawn can promote to.
+    UnknownPromotion(char),
+    /// The origin and target square of a move are the same.
+    SameSquare,
+}
+
+/// Error returned when square or move notation can't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotationError {
+    /// The text that failed to parse.
+    pub text: String,
+    /// Offset, in characters, from the start of the text to where the error was found.
+    pub offset: usize,
+    pub kind: NotationErrorKind,
+}
+
+/// A field of a FEN record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FenField {
+    PiecePlacement,
+    ActiveColor,
+    CastlingRights,
+    EnPassantSquare,
+    HalfmoveClock,
+    FullmoveNumber,
+}
+
+/// What went wrong when loading a FEN record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FenErrorKind {
+    /// The field is missing.
+    MissingField,
+    /// There is text after the last field.
+    TooManyFields,
+    /// The character is not allowed in the field.
+    UnexpectedSymbol(char),
+    /// A rank describes more than 8 squares.
+    TooManyFiles,
+    /// A rank describes less than 8 squares.
+    TooFewFiles,
+    /// The piece placement describes more than 8 ranks.
+    TooManyRanks,
+    /// The piece placement describes less than 8 ranks.
+    TooFewRanks,
+    /// The field is not a valid number.
+    InvalidNumber,
+    /// There is not exactly one king of each color.
+    InvalidKingCount,
+    /// There is a pawn on the first or last rank.
+    PawnOnBackRank,
+    /// A castling right doesn't match the position of the king and rooks.
+    InconsistentCastlingRights,
+    /// The side that is not to move is in check.
+    OpponentInCheck,
+    /// No pawn could have just moved two squares past the en passant square.
+    InvalidEnPassantSquare,
+}
+
+/// Error returned when a FEN record is malformed or describes an impossible position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FenError {
+    /// Field of the record where the error was found.
+    pub field: FenField,
+    /// Offset, in characters, from the start of the record to where the error was found.
+    pub offset: usize,
+    pub kind: FenErrorKind,
+}
+
+impl fmt::Display for FenErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenErrorKind::MissingField => write!(f, "missing field"),
+            FenErrorKind::TooManyFields => write!(f, "unexpected text after the last field"),
+            FenErrorKind::UnexpectedSymbol(symbol) => write!(f, "unexpected symbol '{}'", symbol),
+            FenErrorKind::TooManyFiles => write!(f, "rank with more than 8 squares"),
+            FenErrorKind::TooFewFiles => write!(f, "rank with less than 8 squares"),
+            FenErrorKind::TooManyRanks => write!(f, "more than 8 ranks"),
+            FenErrorKind::TooFewRanks => write!(f, "less than 8 ranks"),
+            FenErrorKind::InvalidNumber => write!(f, "invalid number"),
+            FenErrorKind::InvalidKingCount => write!(f, "each color must have exactly one king"),
+            FenErrorKind::PawnOnBackRank => write!(f, "pawn on the first or last rank"),
+            FenErrorKind::InconsistentCastlingRights => {
+                write!(
+                    f,
+                    "castling right without king and rook on their original squares"
+                )
+            }
+            FenErrorKind::OpponentInCheck => write!(f, "the side not to move is in check"),
+            FenErrorKind::InvalidEnPassantSquare => write!(f, "impossible en passant square"),
+        }
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid FEN, {} in field {:?} at offset {}",
+            self.kind, self.field, self.offset
+        )
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl fmt::Display for NotationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotationErrorKind::InvalidLength => write!(f, "unexpected number of characters"),
+            NotationErrorKind::UnknownFile(symbol) => write!(
+                f,
+                "unknown file coordinate '{}', expected a, b, c, d, e, f, g or h",
+                symbol
+            ),
+            NotationErrorKind::UnknownRank(symbol) => write!(
+                f,
+                "unknown rank coordinate '{}', expected 1, 2, 3, 4, 5, 6, 7 or 8",
+                symbol
+            ),
+            NotationErrorKind::UnknownPromotion(symbol) => write!(
+                f,
+                "unknown promotion piece '{}', expected n, b, r or q",
+                symbol
+            ),
+            NotationErrorKind::SameSquare => {
+                write!(f, "origin and target square cannot be the same")
+            }
+        }
+    }
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to parse '{}', {} at offset {}",
+            self.text, self.kind, self.offset
+        )
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Notation(error) => error.fmt(f),
+            Error::Fen(error) => error.fmt(f),
+            Error::IllegalMove(mov) => write!(f, "Move '{}' is not legal in this position", mov),
+            Error::InvalidBoard => write!(f, "The board is not in a valid state"),
+            Error::InvalidBook => write!(f, "The opening book is truncated or corrupted"),
+            Error::InvalidTablebase => write!(f, "The tablebase file is truncated or corrupted"),
+            Error::Io(error) => write!(f, "IO error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Notation(error) => Some(error),
+            Error::Fen(error) => Some(error),
+            Error::Io(error) => Some(error),
+            Error::IllegalMove(_)
+            | Error::InvalidBoard
+            | Error::InvalidBook
+            | Error::InvalidTablebase => None,
+        }
+    }
+}
+
+impl From<NotationError> for Error {
+    fn from(error: NotationError) -> Self {
+        Error::Notation(error)
+    }
+}
+
+impl From<FenError> for Error {
+    fn from(error: FenError) -> Self {
+        Error::Fen(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
 }
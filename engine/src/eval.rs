@@ -0,0 +1,566 @@
+//! Static evaluation.
+//!
+//! Every term is scored twice, once for the middlegame and once for the endgame, and the
+//! two are blended according to the material left on the board: a position with all its
+//! pieces is scored as a middlegame, one with only kings and pawns as an endgame.
+//!
+//! Material and piece-square tables only depend on where each piece stands, so their sum
+//! is kept by `Board::make_move` the same way as the Zobrist hash, see `Board::psqt`. The
+//! other terms (pawn structure, mobility, king safety and the bishop pair) depend on how
+//! the pieces interact and are computed on every call to `evaluate`.
+//!
+//! Scores are in centipawns. The piece values and tables come from Ronald Friederich's
+//! PeSTO, tuned to work together.
+
+use crate::attacks::{
+    bishop_attacks, king_attacks, knight_attacks, pawn_attacks, queen_attacks, rook_attacks,
+};
+use crate::bitboard::{Board, Square};
+use crate::bits;
+use crate::piece::{Color, Piece, PieceType};
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+/// A middlegame and an endgame score.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// Blends the middlegame and endgame scores for a position at `phase`, see `phase`.
+    pub fn taper(self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Self;
+
+    fn mul(self, factor: i32) -> Self {
+        Self::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+/// Phase of a position with every piece on the board.
+pub const MAX_PHASE: i32 = 24;
+
+const DOUBLED_PAWN: Score = Score::new(-10, -20);
+const ISOLATED_PAWN: Score = Score::new(-10, -15);
+/// Bonus of a passed pawn by rank, counted from its own side.
+const PASSED_PAWN: [Score; 8] = [
+    Score::new(0, 0),
+    Score::new(2, 8),
+    Score::new(5, 12),
+    Score::new(10, 20),
+    Score::new(20, 40),
+    Score::new(35, 70),
+    Score::new(60, 110),
+    Score::new(0, 0),
+];
+const BISHOP_PAIR: Score = Score::new(30, 50);
+/// Bonus of a piece for each square it attacks beyond the usual number, given second.
+const MOBILITY: [(Score, i32); 4] = [
+    (Score::new(4, 4), 4),
+    (Score::new(5, 5), 7),
+    (Score::new(2, 4), 7),
+    (Score::new(1, 2), 14),
+];
+/// Weight of an attack on a square next to the enemy king, by attacker.
+const KING_ATTACK_WEIGHTS: [i32; 4] = [2, 2, 3, 5];
+const PAWN_SHIELD: Score = Score::new(10, 0);
+
+/// Returns the value of a piece of type `piece_type`.
+pub fn piece_value(piece_type: PieceType) -> Score {
+    match piece_type {
+        PieceType::Pawn => Score::new(82, 94),
+        PieceType::Knight => Score::new(337, 281),
+        PieceType::Bishop => Score::new(365, 297),
+        PieceType::Rook => Score::new(477, 512),
+        PieceType::Queen => Score::new(1025, 936),
+        PieceType::King => Score::new(0, 0),
+    }
+}
+
+// Piece-square tables, laid out as seen from white's side: a8 first and h1 last.
+
+#[rustfmt::skip]
+const PAWN_MG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const PAWN_EG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_MG: [i32; 64] = [
+   -167, -89, -34, -49,  61, -97, -15,-107,
+    -73, -41,  72,  36,  23,  62,   7, -17,
+    -47,  60,  37,  65,  84, 129,  73,  44,
+     -9,  17,  19,  53,  37,  69,  18,  22,
+    -13,   4,  16,  13,  28,  19,  21,  -8,
+    -23,  -9,  12,  10,  19,  17,  25, -16,
+    -29, -53, -12,  -3,  -1,  18, -14, -19,
+   -105, -21, -58, -33, -17, -28, -19, -23,
+];
+
+#[rustfmt::skip]
+const KNIGHT_EG: [i32; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+#[rustfmt::skip]
+const BISHOP_MG: [i32; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+#[rustfmt::skip]
+const BISHOP_EG: [i32; 64] = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+
+#[rustfmt::skip]
+const ROOK_MG: [i32; 64] = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+
+#[rustfmt::skip]
+const ROOK_EG: [i32; 64] = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+
+#[rustfmt::skip]
+const QUEEN_MG: [i32; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+#[rustfmt::skip]
+const QUEEN_EG: [i32; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+#[rustfmt::skip]
+const KING_MG: [i32; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+#[rustfmt::skip]
+const KING_EG: [i32; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+/// Returns the value of `piece` standing on `square`, positive for white pieces and
+/// negative for black ones.
+pub fn piece_square(piece: Piece, square: Square) -> Score {
+    let (mg, eg) = match piece.piece_type() {
+        PieceType::Pawn => (&PAWN_MG, &PAWN_EG),
+        PieceType::Knight => (&KNIGHT_MG, &KNIGHT_EG),
+        PieceType::Bishop => (&BISHOP_MG, &BISHOP_EG),
+        PieceType::Rook => (&ROOK_MG, &ROOK_EG),
+        PieceType::Queen => (&QUEEN_MG, &QUEEN_EG),
+        PieceType::King => (&KING_MG, &KING_EG),
+    };
+    // The tables start from the eighth rank, which is where black pieces see the first.
+    let index = match piece.color() {
+        Color::White => square.index ^ 56,
+        Color::Black => square.index,
+    } as usize;
+    let score = piece_value(piece.piece_type()) + Score::new(mg[index], eg[index]);
+    match piece.color() {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+/// Returns the game phase of `board`, from `MAX_PHASE` with all pieces on the board down to
+/// 0 with only kings and pawns.
+pub fn phase(board: &Board) -> i32 {
+    let phase = [
+        (PieceType::Knight, 1),
+        (PieceType::Bishop, 1),
+        (PieceType::Rook, 2),
+        (PieceType::Queen, 4),
+    ]
+    .into_iter()
+    .map(|(piece_type, weight)| {
+        let pieces = board.pieces(Piece::new(piece_type, Color::White))
+            | board.pieces(Piece::new(piece_type, Color::Black));
+        weight * pieces.count_ones() as i32
+    })
+    .sum::<i32>();
+    // Promotions can bring the phase above its starting value.
+    phase.min(MAX_PHASE)
+}
+
+/// Returns the squares of `file`.
+fn file_mask(file: u32) -> u64 {
+    0x8080_8080_8080_8080 >> file
+}
+
+/// Returns the squares of `file` and of the files next to it.
+fn adjacent_files(file: u32) -> u64 {
+    let mask = file_mask(file);
+    mask | (mask << 1 & !file_mask(7)) | (mask >> 1 & !file_mask(0))
+}
+
+/// Returns the squares on the ranks in front of `square` from the side of `color`.
+fn forward_ranks(square: Square, color: Color) -> u64 {
+    let rank = square.get_rank();
+    match color {
+        // Squares with an index of at least `8 * (rank + 1)`.
+        Color::White => u64::MAX.checked_shr(8 * (rank + 1)).unwrap_or(0),
+        // Squares with an index below `8 * rank`.
+        Color::Black => !u64::MAX.checked_shr(8 * rank).unwrap_or(0),
+    }
+}
+
+/// Returns the squares attacked by the pawns of `color`.
+fn pawn_attack_span(board: &Board, color: Color) -> u64 {
+    let mut pawns = board.pieces(Piece::Pawn(color));
+    let mut attacks = 0;
+    while pawns != 0 {
+        attacks |= pawn_attacks(bits::pop_msb(&mut pawns), color);
+    }
+    attacks
+}
+
+/// Scores doubled, isolated and passed pawns of `color`.
+fn pawn_structure(board: &Board, color: Color) -> Score {
+    let pawns = board.pieces(Piece::Pawn(color));
+    let enemy_pawns = board.pieces(Piece::Pawn(!color));
+    let mut score = Score::default();
+    for file in 0..8 {
+        let count = (pawns & file_mask(file)).count_ones() as i32;
+        if count > 1 {
+            score += DOUBLED_PAWN * (count - 1);
+        }
+        if count > 0 && pawns & adjacent_files(file) & !file_mask(file) == 0 {
+            score += ISOLATED_PAWN * count;
+        }
+    }
+
+    let mut remaining = pawns;
+    while remaining != 0 {
+        let square = Square {
+            index: bits::pop_msb(&mut remaining),
+        };
+        let span = adjacent_files(square.get_file()) & forward_ranks(square, color);
+        // Pawns behind one of the same color on its file aren't counted twice.
+        let blocked_by_own = pawns & file_mask(square.get_file()) & forward_ranks(square, color);
+        if enemy_pawns & span == 0 && blocked_by_own == 0 {
+            let rank = match color {
+                Color::White => square.get_rank(),
+                Color::Black => 7 - square.get_rank(),
+            };
+            score += PASSED_PAWN[rank as usize];
+        }
+    }
+    score
+}
+
+/// Scores the mobility of the pieces of `color` and their attacks on the enemy king.
+fn mobility_and_king_attacks(board: &Board, color: Color) -> Score {
+    let occupancy = board.occupancy();
+    // Squares held by enemy pawns aren't worth going to.
+    let area = !board.color_occupancy(color) & !pawn_attack_span(board, !color);
+    let king_zone = board
+        .king_square(!color)
+        .map_or(0, |king| king_attacks(king.index) | king.bitmask());
+
+    let mut score = Score::default();
+    let mut attackers = 0;
+    let mut attack_weight = 0;
+    for (i, piece_type) in PIECE_TYPES[1..5].iter().enumerate() {
+        let mut pieces = board.pieces(Piece::new(*piece_type, color));
+        while pieces != 0 {
+            let index = bits::pop_msb(&mut pieces);
+            let attacks = match piece_type {
+                PieceType::Knight => knight_attacks(index),
+                PieceType::Bishop => bishop_attacks(index, occupancy),
+                PieceType::Rook => rook_attacks(index, occupancy),
+                _ => queen_attacks(index, occupancy),
+            };
+            let (bonus, usual) = MOBILITY[i];
+            score += bonus * ((attacks & area).count_ones() as i32 - usual);
+            if attacks & king_zone != 0 {
+                attackers += 1;
+                attack_weight += KING_ATTACK_WEIGHTS[i] * (attacks & king_zone).count_ones() as i32;
+            }
+        }
+    }
+    // A single piece rarely makes a dangerous attack.
+    if attackers > 1 {
+        score += Score::new(attack_weight * attack_weight / 4, 0);
+    }
+    score
+}
+
+/// Scores the pawns of `color` on the two ranks in front of their king, on its file and
+/// the files next to it.
+fn pawn_shield(board: &Board, color: Color) -> Score {
+    let Some(king) = board.king_square(color) else {
+        return Score::default();
+    };
+    let rank = king.get_rank() as i32;
+    let ranks = match color {
+        Color::White => [rank + 1, rank + 2],
+        Color::Black => [rank - 1, rank - 2],
+    };
+    let shield = ranks
+        .into_iter()
+        .filter(|rank| (0..8).contains(rank))
+        .fold(0, |shield, rank| {
+            shield | 0xff00_0000_0000_0000 >> (8 * rank)
+        })
+        & adjacent_files(king.get_file());
+    PAWN_SHIELD * (board.pieces(Piece::Pawn(color)) & shield).count_ones() as i32
+}
+
+/// Evaluates `board` from the point of view of white.
+fn evaluate_white(board: &Board) -> i32 {
+    let mut score = board.psqt();
+    for (color, sign) in [(Color::White, 1), (Color::Black, -1)] {
+        let mut own = pawn_structure(board, color)
+            + mobility_and_king_attacks(board, color)
+            + pawn_shield(board, color);
+        if bits::more_than_one(board.pieces(Piece::Bishop(color))) {
+            own += BISHOP_PAIR;
+        }
+        score += own * sign;
+    }
+    score.taper(phase(board))
+}
+
+/// Evaluates `board` from the point of view of the side to move.
+pub fn evaluate(board: &Board) -> i32 {
+    let score = evaluate_white(board);
+    match board.color_to_move() {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+impl Board {
+    /// Computes the material and piece-square score of the position from scratch.
+    ///
+    /// `Board::psqt` returns the same value, maintained incrementally.
+    pub fn compute_psqt(&self) -> Score {
+        let mut score = Score::default();
+        for color in [Color::White, Color::Black] {
+            for piece_type in PIECE_TYPES {
+                let piece = Piece::new(piece_type, color);
+                let mut pieces = self.pieces(piece);
+                while pieces != 0 {
+                    let index = bits::pop_msb(&mut pieces);
+                    score += piece_square(piece, Square { index });
+                }
+            }
+        }
+        score
+    }
+}
+
+#[test]
+fn symmetric_evaluation() {
+    use crate::parser::load_position_from_fen;
+
+    assert_eq!(Board::new().psqt(), Score::default());
+    assert_eq!(Board::new().compute_psqt(), Score::default());
+    assert_eq!(evaluate(&Board::new()), 0);
+    assert_eq!(phase(&Board::new()), MAX_PHASE);
+
+    // Mirroring the board and swapping colors gives the same evaluation
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    ] {
+        let board = load_position_from_fen(fen).unwrap();
+        let array = board.piece_array();
+        let mirrored = Board::from_array(
+            &std::array::from_fn(|index| {
+                array[index ^ 56].map(|piece| Piece::new(piece.piece_type(), !piece.color()))
+            }),
+            crate::bitboard::CastleRights::empty(),
+            !board.color_to_move(),
+            None,
+            0,
+            1,
+        );
+        assert_eq!(mirrored.psqt(), -board.psqt(), "{}", fen);
+        assert_eq!(evaluate(&mirrored), evaluate(&board), "{}", fen);
+    }
+}
+
+#[test]
+fn evaluation_terms() {
+    use crate::parser::load_position_from_fen;
+
+    let white = |fen| evaluate_white(&load_position_from_fen(fen).unwrap());
+    // Kings and pawns only, scored as an endgame
+    let board = load_position_from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+    assert_eq!(phase(&board), 0);
+    // Doubled and isolated pawns are worse than connected ones
+    let doubled = pawn_structure(
+        &load_position_from_fen("4k3/2p5/8/8/8/2P5/2P5/4K3 w - - 0 1").unwrap(),
+        Color::White,
+    );
+    assert_eq!(doubled, DOUBLED_PAWN + ISOLATED_PAWN * 2);
+    assert!(
+        white("4k3/pp6/8/8/8/2P5/2P5/4K3 w - - 0 1") < white("4k3/pp6/8/8/8/8/2PP4/4K3 w - - 0 1")
+    );
+    // A passed pawn is worth more the further it is
+    let passed = |fen| pawn_structure(&load_position_from_fen(fen).unwrap(), Color::Black);
+    assert_eq!(
+        passed("4k3/8/8/8/8/3p4/8/4K3 w - - 0 1"),
+        ISOLATED_PAWN + PASSED_PAWN[5]
+    );
+    assert_eq!(passed("4k3/8/8/8/8/3p4/4P3/4K3 w - - 0 1"), ISOLATED_PAWN);
+    assert!(white("4k3/8/8/8/8/3p4/8/4K3 w - - 0 1") < white("4k3/8/3p4/8/8/8/8/4K3 w - - 0 1"));
+    // The same rook, free or boxed in, and pieces closing in on the king
+    let mobility =
+        |fen| mobility_and_king_attacks(&load_position_from_fen(fen).unwrap(), Color::White);
+    assert_eq!(
+        mobility("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"),
+        MOBILITY[2].0 * 3
+    );
+    assert_eq!(
+        mobility("4k3/8/8/8/8/8/P7/R3K3 w - - 0 1"),
+        MOBILITY[2].0 * -4
+    );
+    // A queen and a rook hitting three squares around the king, out of its reach
+    let near = mobility("7k/8/8/5Q2/8/8/6R1/4K3 w - - 0 1");
+    let far = mobility("k7/8/8/5Q2/8/8/6R1/4K3 w - - 0 1");
+    assert_eq!(near - far, Score::new(11 * 11 / 4, 0));
+    // A pawn shield in front of the castled king
+    let board = load_position_from_fen("4k3/8/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(pawn_shield(&board, Color::White), PAWN_SHIELD * 3);
+}
@@ -0,0 +1,259 @@
+//! Game termination rules.
+//!
+//! `Board::outcome` applies the rules that only depend on the current position. Repetitions
+//! need the positions that led to it, so they are detected by `Game`, which keeps the board
+//! together with the moves played on it and the Zobrist hashes of the positions they left.
+
+use crate::bitboard::{Board, Move, Square, Undo};
+use crate::errors::{Error, Result};
+use crate::piece::{Color, Piece, PieceType};
+
+/// Why a game is over, or can be claimed to be.
+///
+/// The fifty-move rule and threefold repetition are draws that a player may claim, while
+/// their seventy-five-move and fivefold counterparts end the game automatically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Checkmate { winner: Color },
+    Stalemate,
+    InsufficientMaterial,
+    SeventyFiveMoveRule,
+    FivefoldRepetition,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+}
+
+impl Outcome {
+    /// Returns the color that won the game, or `None` if it's a draw.
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            Outcome::Checkmate { winner } => Some(winner),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the outcome is a draw.
+    pub fn is_draw(self) -> bool {
+        self.winner().is_none()
+    }
+
+    /// Returns `true` if the game ends on its own, and `false` if a draw must be claimed.
+    pub fn is_automatic(self) -> bool {
+        !matches!(self, Outcome::FiftyMoveRule | Outcome::ThreefoldRepetition)
+    }
+}
+
+impl Board {
+    /// Returns how the game ends in this position, ignoring repetitions, or `None` if it goes
+    /// on.
+    ///
+    /// A checkmate delivered on the move that reaches the halfmove limit takes precedence
+    /// over the fifty and seventy-five-move rules.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.get_legal_moves().is_empty() {
+            return Some(match self.in_check() {
+                true => Outcome::Checkmate {
+                    winner: !self.color_to_move(),
+                },
+                false => Outcome::Stalemate,
+            });
+        }
+        if self.is_insufficient_material() {
+            Some(Outcome::InsufficientMaterial)
+        } else if self.halfmove_clock() >= 150 {
+            Some(Outcome::SeventyFiveMoveRule)
+        } else if self.halfmove_clock() >= 100 {
+            Some(Outcome::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if neither side can possibly checkmate: a lone king against a king
+    /// with at most one minor piece, or kings and bishops that all stand on squares of the
+    /// same color.
+    pub fn is_insufficient_material(&self) -> bool {
+        let pieces = |piece_type| {
+            self.pieces(Piece::new(piece_type, Color::White))
+                | self.pieces(Piece::new(piece_type, Color::Black))
+        };
+        if pieces(PieceType::Pawn) | pieces(PieceType::Rook) | pieces(PieceType::Queen) != 0 {
+            return false;
+        }
+        let kings = pieces(PieceType::King);
+        let minors = pieces(PieceType::Knight) | pieces(PieceType::Bishop);
+        if minors.count_ones() <= 1 {
+            return true;
+        }
+        let bishops = pieces(PieceType::Bishop);
+        let light_squares = (0..64)
+            .map(|index| Square { index })
+            .filter(|square| (square.get_rank() + square.get_file()) % 2 == 1)
+            .fold(0, |squares, square| squares | square.bitmask());
+        minors & !kings == bishops
+            && (bishops & light_squares == 0 || bishops & !light_squares == 0)
+    }
+}
+
+/// A board together with the moves played on it since its initial position.
+#[derive(Clone, Debug)]
+pub struct Game {
+    board: Board,
+    moves: Vec<Move>,
+    undos: Vec<Undo>,
+    /// The hash of the position before each move in `moves`.
+    hashes: Vec<u64>,
+}
+
+impl Game {
+    /// Returns a game starting from `board`.
+    pub fn new(board: Board) -> Self {
+        Self {
+            board,
+            moves: Vec::new(),
+            undos: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Returns the current position.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Returns the moves played so far.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Returns the hashes of the positions before each of the moves played so far.
+    pub(crate) fn hashes(&self) -> &[u64] {
+        &self.hashes
+    }
+
+    /// Plays `mov` if it's legal in the current position.
+    pub fn play(&mut self, mov: Move) -> Result<()> {
+        if !self.board.get_legal_moves().contains(&mov) {
+            return Err(Error::IllegalMove(mov));
+        }
+        self.hashes.push(self.board.hash());
+        self.undos.push(self.board.make_move(mov));
+        self.moves.push(mov);
+        Ok(())
+    }
+
+    /// Takes back the last move and returns it, or `None` if no move was played.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mov = self.moves.pop()?;
+        let undo = self.undos.pop()?;
+        self.hashes.pop();
+        self.board.undo_move(mov, undo);
+        Some(mov)
+    }
+
+    /// Returns how many times the current position has occurred, including now.
+    pub fn repetitions(&self) -> usize {
+        let hash = self.board.hash();
+        // Positions before the last capture or pawn move can't repeat.
+        let reversible = (self.board.halfmove_clock() as usize).min(self.hashes.len());
+        1 + self.hashes[self.hashes.len() - reversible..]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .filter(|&&previous| previous == hash)
+            .count()
+    }
+
+    /// Returns how the game ends, or can be claimed to end, in the current position, or
+    /// `None` if it goes on.
+    pub fn outcome(&self) -> Option<Outcome> {
+        let outcome = self.board.outcome();
+        if let Some(Outcome::Checkmate { .. } | Outcome::Stalemate) = outcome {
+            return outcome;
+        }
+        let repetitions = self.repetitions();
+        let repetition = match repetitions {
+            5.. => Some(Outcome::FivefoldRepetition),
+            3.. => Some(Outcome::ThreefoldRepetition),
+            _ => None,
+        };
+        // Outcomes that end the game automatically come first.
+        [outcome, repetition]
+            .into_iter()
+            .flatten()
+            .min_by_key(|outcome| !outcome.is_automatic())
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new(Board::new())
+    }
+}
+
+#[test]
+fn checkmate_and_stalemate() {
+    use crate::parser::load_position_from_fen;
+
+    // Fool's mate
+    let mut game = Game::default();
+    for text in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+        game.play(game.board().move_from_notation(text).unwrap())
+            .unwrap();
+    }
+    assert_eq!(
+        game.outcome(),
+        Some(Outcome::Checkmate {
+            winner: Color::Black
+        })
+    );
+    let board = load_position_from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(board.outcome(), Some(Outcome::Stalemate));
+    // Mate on the hundredth halfmove still wins
+    let board = load_position_from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 100 80").unwrap();
+    assert_eq!(board.outcome().unwrap().winner(), Some(Color::White));
+    assert_eq!(Board::new().outcome(), None);
+}
+
+#[test]
+fn draw_rules() {
+    use crate::parser::load_position_from_fen;
+
+    let board = load_position_from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 100 80").unwrap();
+    assert_eq!(board.outcome(), Some(Outcome::FiftyMoveRule));
+    let board = load_position_from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 150 100").unwrap();
+    assert_eq!(board.outcome(), Some(Outcome::SeventyFiveMoveRule));
+
+    for (fen, insufficient) in [
+        ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+        ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", true),
+        ("4kb2/8/8/8/8/8/8/4K3 w - - 0 1", true),
+        // Bishops on squares of the same color, and of different colors
+        ("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", true),
+        ("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1", false),
+        ("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", false),
+        ("4kn2/8/8/8/8/8/8/2B1K3 w - - 0 1", false),
+        ("4k3/8/8/8/8/8/8/4K2R w - - 0 1", false),
+    ] {
+        let board = load_position_from_fen(fen).unwrap();
+        assert_eq!(board.is_insufficient_material(), insufficient, "{}", fen);
+    }
+
+    // Shuffling the knights back and forth
+    let mut game = Game::default();
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+    for (i, text) in shuffle.iter().cycle().take(16).enumerate() {
+        assert_eq!(game.repetitions(), 1 + i / 4);
+        game.play(game.board().move_from_notation(text).unwrap())
+            .unwrap();
+    }
+    assert_eq!(game.repetitions(), 5);
+    assert_eq!(game.outcome(), Some(Outcome::FivefoldRepetition));
+    game.undo();
+    assert_eq!(game.outcome(), Some(Outcome::ThreefoldRepetition));
+    for _ in 0..8 {
+        game.undo();
+    }
+    assert_eq!(game.outcome(), None);
+}
@@ -5,3 +5,16 @@ pub mod errors;
 pub mod bits;
 pub mod castle;
 pub mod init;
+pub mod movegen;
+pub mod perft;
+pub mod attacks;
+pub mod game;
+pub mod zobrist;
+pub mod book;
+pub mod search;
+pub mod transposition;
+pub mod eval;
+pub mod time;
+pub mod movepick;
+pub mod see;
+pub mod tablebase;
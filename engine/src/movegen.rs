@@ -0,0 +1,479 @@
+//! Legal move generation.
+//!
+//! Moves are generated from the precomputed tables of the `attacks` module and are strictly
+//! legal, without playing them on the board first: the king only steps to squares that aren't
+//! attacked, pinned pieces stay on their pin ray, and out of check the other pieces must
+//! capture or block the checker.
+
+use crate::attacks::{
+    between, bishop_attacks, king_attacks, knight_attacks, line, pawn_attacks, piece_attacks,
+    rook_attacks,
+};
+use crate::bitboard::{Board, Move, MoveKind, Square};
+use crate::bits;
+use crate::errors::{Error, Result};
+use crate::piece::{Color, Piece, PieceType};
+
+/// Pushes a move of the given kind from `origin` to every square in `targets`.
+fn push_moves(moves: &mut Vec<Move>, origin: u32, mut targets: u64, kind: MoveKind) {
+    while targets != 0 {
+        let target = bits::pop_msb(&mut targets);
+        moves.push(Move::new(
+            Square { index: origin },
+            Square { index: target },
+            kind,
+        ));
+    }
+}
+
+/// Pushes a pawn move, expanding it into the four possible promotions when the pawn
+/// reaches the last rank.
+fn push_pawn_move(moves: &mut Vec<Move>, origin: u32, target: u32, is_capture: bool) {
+    let (origin, target) = (Square { index: origin }, Square { index: target });
+    if target.get_rank() == 0 || target.get_rank() == 7 {
+        for piece_type in [
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+        ] {
+            let kind = match is_capture {
+                true => MoveKind::PromotionCapture(piece_type),
+                false => MoveKind::Promotion(piece_type),
+            };
+            moves.push(Move::new(origin, target, kind));
+        }
+    } else {
+        let kind = match is_capture {
+            true => MoveKind::Capture,
+            false => MoveKind::Quiet,
+        };
+        moves.push(Move::new(origin, target, kind));
+    }
+}
+
+impl Board {
+    /// Returns every legal move for the side to move.
+    ///
+    /// Castling moves are represented by the king moving two squares towards the rook.
+    pub fn get_legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        let color = self.color_to_move();
+        let checkers = self.checkers();
+
+        self.generate_king_moves(&mut moves);
+        // Only the king can get out of a double check.
+        if bits::more_than_one(checkers) {
+            return moves;
+        }
+        // Out of a single check, the other pieces must capture the checker or block it.
+        let mut targets = !self.color_occupancy(color);
+        if let (Some(king), true) = (self.king_square(color), checkers != 0) {
+            targets &= checkers | between(king.index, checkers.leading_zeros());
+        }
+        self.generate_pawn_moves(&mut moves, targets);
+        self.generate_piece_moves(&mut moves, targets);
+        if checkers == 0 {
+            self.generate_castling_moves(&mut moves);
+        }
+        moves
+    }
+
+    /// Returns the legal move described by `text` in UCI notation (see `Move::from_notation`),
+    /// with the `MoveKind` that corresponds to the current position.
+    pub fn move_from_notation(&self, text: &str) -> Result<Move> {
+        let parsed = Move::from_notation(text)?;
+        self.get_legal_moves()
+            .into_iter()
+            .find(|mov| {
+                mov.origin() == parsed.origin()
+                    && mov.target() == parsed.target()
+                    && mov.promotion() == parsed.promotion()
+            })
+            .ok_or(Error::IllegalMove(parsed))
+    }
+
+    /// Returns the pieces of both colors attacking `square`.
+    ///
+    /// Sliding attacks are computed against `occupancy` instead of the board's own occupancy,
+    /// which allows looking through pieces that are about to move.
+    pub fn attackers_to(&self, square: Square, occupancy: u64) -> u64 {
+        let index = square.index;
+        let rooks = self.pieces(Piece::Rook(Color::White))
+            | self.pieces(Piece::Rook(Color::Black))
+            | self.pieces(Piece::Queen(Color::White))
+            | self.pieces(Piece::Queen(Color::Black));
+        let bishops = self.pieces(Piece::Bishop(Color::White))
+            | self.pieces(Piece::Bishop(Color::Black))
+            | self.pieces(Piece::Queen(Color::White))
+            | self.pieces(Piece::Queen(Color::Black));
+        let knights =
+            self.pieces(Piece::Knight(Color::White)) | self.pieces(Piece::Knight(Color::Black));
+        let kings = self.pieces(Piece::King(Color::White)) | self.pieces(Piece::King(Color::Black));
+
+        // A pawn attacks the square if a pawn of the other color on the square would attack it.
+        knight_attacks(index) & knights
+            | king_attacks(index) & kings
+            | pawn_attacks(index, Color::Black) & self.pieces(Piece::Pawn(Color::White))
+            | pawn_attacks(index, Color::White) & self.pieces(Piece::Pawn(Color::Black))
+            | rook_attacks(index, occupancy) & rooks
+            | bishop_attacks(index, occupancy) & bishops
+    }
+
+    /// Returns `true` if `square` is attacked by any piece of `by_color`.
+    pub fn is_square_attacked(&self, square: Square, by_color: Color) -> bool {
+        self.attackers_to(square, self.occupancy()) & self.color_occupancy(by_color) != 0
+    }
+
+    /// Returns the enemy pieces giving check to the king of the side to move.
+    pub fn checkers(&self) -> u64 {
+        let color = self.color_to_move();
+        self.king_square(color).map_or(0, |king| {
+            self.attackers_to(king, self.occupancy()) & self.color_occupancy(!color)
+        })
+    }
+
+    /// Returns `true` if the king of the side to move is in check.
+    pub fn in_check(&self) -> bool {
+        self.checkers() != 0
+    }
+
+    /// Returns the pieces of the side to move that are pinned to their king, and so can
+    /// only move along their pin ray.
+    pub fn pinned(&self) -> u64 {
+        let color = self.color_to_move();
+        self.blockers(color) & self.color_occupancy(color)
+    }
+
+    /// Returns the squares the piece on `square` can move to without exposing its king: the
+    /// line through the king and the piece if it's pinned, and every square otherwise.
+    pub fn pin_ray(&self, square: Square) -> u64 {
+        match self.king_square(self.color_to_move()) {
+            Some(king) if self.pinned() & square.bitmask() != 0 => line(king.index, square.index),
+            _ => !0,
+        }
+    }
+
+    /// Returns the pieces of the side to move that give a discovered check by moving off
+    /// the line between one of their sliders and the enemy king.
+    pub fn discovered_check_candidates(&self) -> u64 {
+        let color = self.color_to_move();
+        self.blockers(!color) & self.color_occupancy(color)
+    }
+
+    /// Returns the pieces of either color that are the only piece between the king of
+    /// `color` and an enemy slider.
+    fn blockers(&self, color: Color) -> u64 {
+        let Some(king) = self.king_square(color) else {
+            return 0;
+        };
+        let occupancy = self.occupancy();
+        let queens = self.pieces(Piece::Queen(!color));
+        // Sliders that would attack the king on an empty board.
+        let mut snipers = rook_attacks(king.index, 0) & (self.pieces(Piece::Rook(!color)) | queens)
+            | bishop_attacks(king.index, 0) & (self.pieces(Piece::Bishop(!color)) | queens);
+
+        let mut blockers = 0;
+        while snipers != 0 {
+            let ray = between(king.index, bits::pop_msb(&mut snipers)) & occupancy;
+            if bits::only_one(ray) {
+                blockers |= ray;
+            }
+        }
+        blockers
+    }
+
+    /// Returns the square of the king of `color`, if there is one on the board.
+    pub fn king_square(&self, color: Color) -> Option<Square> {
+        let king = self.pieces(Piece::King(color));
+        (king != 0).then(|| Square {
+            index: king.leading_zeros(),
+        })
+    }
+
+    /// Returns `true` if the king of `color` is attacked by the opponent.
+    pub(crate) fn is_king_attacked(&self, color: Color) -> bool {
+        self.king_square(color)
+            .is_some_and(|king| self.is_square_attacked(king, !color))
+    }
+
+    fn generate_king_moves(&self, moves: &mut Vec<Move>) {
+        let color = self.color_to_move();
+        let Some(king) = self.king_square(color) else {
+            return;
+        };
+        let enemy = self.color_occupancy(!color);
+        // The king must not hide from a slider behind its own square.
+        let occupancy = self.occupancy() & !king.bitmask();
+
+        let mut targets = king_attacks(king.index) & !self.color_occupancy(color);
+        while targets != 0 {
+            let target = Square {
+                index: bits::pop_msb(&mut targets),
+            };
+            if self.attackers_to(target, occupancy) & enemy == 0 {
+                let kind = match enemy & target.bitmask() != 0 {
+                    true => MoveKind::Capture,
+                    false => MoveKind::Quiet,
+                };
+                moves.push(Move::new(king, target, kind));
+            }
+        }
+    }
+
+    /// Generates the moves of knights, bishops, rooks and queens landing on `targets`.
+    fn generate_piece_moves(&self, moves: &mut Vec<Move>, targets: u64) {
+        let color = self.color_to_move();
+        let enemy = self.color_occupancy(!color);
+        let occupancy = self.occupancy();
+        let pinned = self.pinned();
+        let king = self.king_square(color);
+
+        for piece_type in [
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+        ] {
+            let piece = Piece::new(piece_type, color);
+            let mut pieces = self.pieces(piece);
+            while pieces != 0 {
+                let origin = bits::pop_msb(&mut pieces);
+                let mut targets = piece_attacks(piece, origin, occupancy) & targets;
+                if let (Some(king), true) = (king, pinned & Square { index: origin }.bitmask() != 0)
+                {
+                    targets &= line(king.index, origin);
+                }
+                push_moves(moves, origin, targets & !occupancy, MoveKind::Quiet);
+                push_moves(moves, origin, targets & enemy, MoveKind::Capture);
+            }
+        }
+    }
+
+    /// Generates the pawn moves landing on `targets`, and the legal en passant captures.
+    fn generate_pawn_moves(&self, moves: &mut Vec<Move>, targets: u64) {
+        let color = self.color_to_move();
+        let enemy = self.color_occupancy(!color);
+        let occupancy = self.occupancy();
+        let pinned = self.pinned();
+        let king = self.king_square(color);
+        let (forward, start_rank): (i32, u32) = match color {
+            Color::White => (8, 1),
+            Color::Black => (-8, 6),
+        };
+
+        let is_empty = |index: u32| occupancy & Square { index }.bitmask() == 0;
+
+        let mut pawns = self.pieces(Piece::Pawn(color));
+        while pawns != 0 {
+            let origin = bits::pop_msb(&mut pawns);
+            let single = origin as i32 + forward;
+            if !(0..64).contains(&single) {
+                continue;
+            }
+            let single = single as u32;
+            let mut targets = targets;
+            if let (Some(king), true) = (king, pinned & Square { index: origin }.bitmask() != 0) {
+                targets &= line(king.index, origin);
+            }
+            let is_target = |index: u32| targets & Square { index }.bitmask() != 0;
+
+            if is_empty(single) {
+                if is_target(single) {
+                    push_pawn_move(moves, origin, single, false);
+                }
+                let double = (single as i32 + forward) as u32;
+                if origin / 8 == start_rank && is_empty(double) && is_target(double) {
+                    push_moves(
+                        moves,
+                        origin,
+                        Square { index: double }.bitmask(),
+                        MoveKind::DoublePawnPush,
+                    );
+                }
+            }
+            let mut captures = pawn_attacks(origin, color) & enemy & targets;
+            while captures != 0 {
+                push_pawn_move(moves, origin, bits::pop_msb(&mut captures), true);
+            }
+        }
+
+        self.generate_en_passant_moves(moves);
+    }
+
+    /// Generates the legal en passant captures.
+    ///
+    /// Both pawns leave their squares at once, which can uncover an attack on the king that
+    /// pins don't describe, so the position after the capture is checked directly.
+    fn generate_en_passant_moves(&self, moves: &mut Vec<Move>) {
+        let color = self.color_to_move();
+        let (Some(target), Some(king)) = (self.en_passant_square(), self.king_square(color)) else {
+            return;
+        };
+        let captured = match color {
+            Color::White => Square {
+                index: target.index - 8,
+            },
+            Color::Black => Square {
+                index: target.index + 8,
+            },
+        };
+
+        let mut pawns = pawn_attacks(target.index, !color) & self.pieces(Piece::Pawn(color));
+        while pawns != 0 {
+            let origin = Square {
+                index: bits::pop_msb(&mut pawns),
+            };
+            let occupancy =
+                self.occupancy() ^ origin.bitmask() ^ captured.bitmask() | target.bitmask();
+            let attackers = self.attackers_to(king, occupancy)
+                & self.color_occupancy(!color)
+                & !captured.bitmask();
+            if attackers == 0 {
+                moves.push(Move::new(origin, target, MoveKind::EnPassant));
+            }
+        }
+    }
+
+    fn generate_castling_moves(&self, moves: &mut Vec<Move>) {
+        let color = self.color_to_move();
+        let occupancy = self.occupancy();
+        let rooks = self.pieces(Piece::Rook(color));
+        let king = match color {
+            Color::White => 4,
+            Color::Black => 60,
+        };
+        let is_empty = |index: u32| occupancy & Square { index }.bitmask() == 0;
+        let has_rook = |index: u32| rooks & Square { index }.bitmask() != 0;
+        let has_king =
+            |index: u32| self.pieces(Piece::King(color)) & Square { index }.bitmask() != 0;
+
+        if !has_king(king) || self.is_square_attacked(Square { index: king }, !color) {
+            return;
+        }
+        if self.can_castle_kingside(color)
+            && is_empty(king + 1)
+            && is_empty(king + 2)
+            && has_rook(king + 3)
+            && !self.is_square_attacked(Square { index: king + 1 }, !color)
+            && !self.is_square_attacked(Square { index: king + 2 }, !color)
+        {
+            let (origin, target) = (Square { index: king }, Square { index: king + 2 });
+            moves.push(Move::new(origin, target, MoveKind::KingCastle));
+        }
+        if self.can_castle_queenside(color)
+            && is_empty(king - 1)
+            && is_empty(king - 2)
+            && is_empty(king - 3)
+            && has_rook(king - 4)
+            && !self.is_square_attacked(Square { index: king - 1 }, !color)
+            && !self.is_square_attacked(Square { index: king - 2 }, !color)
+        {
+            let (origin, target) = (Square { index: king }, Square { index: king - 2 });
+            moves.push(Move::new(origin, target, MoveKind::QueenCastle));
+        }
+    }
+}
+
+#[test]
+fn legal_moves_starting_position() {
+    assert_eq!(Board::new().get_legal_moves().len(), 20);
+}
+
+#[test]
+fn legal_moves_special_moves() {
+    use crate::parser::load_position_from_fen;
+
+    // En passant capture on d6
+    let board = load_position_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+    let mov = board.move_from_notation("e5d6").unwrap();
+    assert_eq!(mov.kind(), MoveKind::EnPassant);
+    // Promotions, both by pushing and capturing
+    let board = load_position_from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    let promotions = board
+        .get_legal_moves()
+        .into_iter()
+        .filter(|mov| mov.promotion().is_some())
+        .count();
+    assert_eq!(promotions, 8);
+    // Castling through an attacked square is not allowed
+    let board = load_position_from_fen("r3k2r/8/8/8/8/8/8/R3K1r1 w Qkq - 0 1").unwrap();
+    assert!(!board.get_legal_moves().iter().any(|mov| mov.is_castling()));
+    // Only moves that get out of check
+    let board = load_position_from_fen("4k3/8/8/8/8/8/3q4/R3K3 w Q - 0 1").unwrap();
+    let moves = board.get_legal_moves();
+    assert_eq!(moves.len(), 2);
+    assert!(moves
+        .iter()
+        .any(|mov| mov.to_string() == "e1d2" && mov.is_capture()));
+    assert!(moves.iter().any(|mov| mov.to_string() == "e1f1"));
+}
+
+#[test]
+fn attacks_and_checks() {
+    use crate::parser::load_position_from_fen;
+
+    let square = |text: &str| Square::from_notation(text).unwrap();
+
+    let board = Board::new();
+    assert!(!board.in_check());
+    assert_eq!(board.checkers(), 0);
+    assert!(board.is_square_attacked(square("f3"), Color::White));
+    assert!(!board.is_square_attacked(square("e4"), Color::White));
+    assert_eq!(
+        board.attackers_to(square("f3"), board.occupancy()),
+        square("e2").bitmask() | square("g2").bitmask() | square("g1").bitmask()
+    );
+    // Double check by a knight and a rook
+    let board = load_position_from_fen("4r1k1/8/8/8/3n4/8/4K3/8 w - - 0 1").unwrap();
+    assert!(board.in_check());
+    assert_eq!(
+        board.checkers(),
+        square("e8").bitmask() | square("d4").bitmask()
+    );
+    // The king doesn't block the rook once it steps away from it
+    let occupancy = board.occupancy() & !square("e2").bitmask();
+    assert!(!board.is_square_attacked(square("e1"), Color::Black));
+    assert_eq!(
+        board.attackers_to(square("e1"), occupancy) & board.color_occupancy(Color::Black),
+        square("e8").bitmask()
+    );
+}
+
+#[test]
+fn pins_and_discovered_checks() {
+    use crate::parser::load_position_from_fen;
+
+    let square = |text: &str| Square::from_notation(text).unwrap();
+
+    // The knight on d2 is pinned by the bishop and can't move at all, while the rook on e4
+    // is pinned by the rook on e8 and can still move along the file.
+    let board = load_position_from_fen("4r1k1/8/8/8/1b2R3/8/3N4/4K3 w - - 0 1").unwrap();
+    assert_eq!(
+        board.pinned(),
+        square("d2").bitmask() | square("e4").bitmask()
+    );
+    assert_eq!(board.pin_ray(square("e1")), !0);
+    assert_eq!(
+        board.pin_ray(square("e4")),
+        crate::attacks::line(square("e1").index, square("e8").index)
+    );
+    let moves = board.get_legal_moves();
+    assert!(!moves.iter().any(|mov| mov.origin() == square("d2")));
+    assert_eq!(
+        moves
+            .iter()
+            .filter(|mov| mov.origin() == square("e4"))
+            .count(),
+        6
+    );
+    assert_eq!(board.discovered_check_candidates(), 0);
+    // The knight on e6 uncovers the rook on e4
+    let board = load_position_from_fen("4k3/8/4N3/8/4R3/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(board.discovered_check_candidates(), square("e6").bitmask());
+    assert_eq!(board.pinned(), 0);
+
+    // En passant along a rank would expose the king to the rook
+    let board = load_position_from_fen("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 1").unwrap();
+    assert!(board.move_from_notation("e5d6").is_err());
+}
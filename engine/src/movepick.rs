@@ -0,0 +1,348 @@
+//! Move ordering.
+//!
+//! Alpha-beta prunes the most when the best move of a node is searched first. `MovePicker`
+//! hands out the legal moves of a position in stages, from the most to the least likely to
+//! be best:
+//!
+//! 1. the hash move, the best move found by an earlier search of the position,
+//! 2. captures and promotions that don't lose material according to the static exchange
+//!    evaluation, the most valuable victim first and then the least valuable attacker
+//!    (MVV-LVA),
+//! 3. the killer moves, quiet moves that caused a cutoff at the same ply elsewhere in the
+//!    tree, and the countermove, the quiet move that last refuted the previous move,
+//! 4. the other quiet moves, by how often they caused cutoffs so far (history heuristic),
+//! 5. the captures and promotions that lose material.
+//!
+//! Moves are only scored when their stage is reached, and picked one at a time, so nodes
+//! that are cut off by one of the first moves don't pay for ordering the others.
+//!
+//! The statistics behind the last two stages are gathered in `History` as the search goes.
+
+use crate::bitboard::{Board, Move};
+use crate::piece::{Color, PieceType};
+use crate::search::MAX_PLY;
+
+/// Bound of the history scores.
+const MAX_HISTORY: i32 = 16_384;
+
+/// Statistics on the quiet moves that caused cutoffs, kept for a whole search.
+#[derive(Clone, Debug)]
+pub struct History {
+    killers: Vec<[Option<Move>; 2]>,
+    /// Scores of quiet moves, indexed by the color that plays them, their origin and target.
+    scores: Box<[[[i32; 64]; 64]; 2]>,
+    /// The quiet move that refuted each move, indexed by origin and target of the move.
+    countermoves: Box<[[Option<Move>; 64]; 64]>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    /// Returns empty statistics.
+    pub fn new() -> Self {
+        Self {
+            killers: vec![[None; 2]; MAX_PLY + 1],
+            scores: Box::new([[[0; 64]; 64]; 2]),
+            countermoves: Box::new([[None; 64]; 64]),
+        }
+    }
+
+    /// Forgets every statistic.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Prepares the statistics for a new search: the killer moves are forgotten, since the
+    /// plies don't match those of the previous search anymore, and the scores are halved.
+    pub fn age(&mut self) {
+        self.killers.fill([None; 2]);
+        for score in self.scores.iter_mut().flatten().flatten() {
+            *score /= 2;
+        }
+    }
+
+    /// Returns the killer moves of `ply`.
+    pub fn killers(&self, ply: usize) -> [Option<Move>; 2] {
+        self.killers[ply]
+    }
+
+    /// Returns the quiet move that last refuted `previous`.
+    pub fn countermove(&self, previous: Option<Move>) -> Option<Move> {
+        previous.and_then(|mov| {
+            self.countermoves[mov.origin().index as usize][mov.target().index as usize]
+        })
+    }
+
+    /// Returns the history score of the quiet move `mov` played by `color`.
+    pub fn score(&self, color: Color, mov: Move) -> i32 {
+        self.scores[color as usize][mov.origin().index as usize][mov.target().index as usize]
+    }
+
+    /// Records that the quiet move `mov`, played by `color` at `ply` after `previous`,
+    /// caused a cutoff at `depth`, and that the quiet moves in `tried` were searched before
+    /// it without causing one.
+    pub fn update(
+        &mut self,
+        color: Color,
+        ply: usize,
+        previous: Option<Move>,
+        mov: Move,
+        tried: &[Move],
+        depth: i32,
+    ) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mov) {
+            killers[1] = killers[0];
+            killers[0] = Some(mov);
+        }
+        if let Some(previous) = previous {
+            self.countermoves[previous.origin().index as usize][previous.target().index as usize] =
+                Some(mov);
+        }
+
+        let bonus = (depth * depth).min(400);
+        self.add(color, mov, bonus);
+        for &tried in tried {
+            self.add(color, tried, -bonus);
+        }
+    }
+
+    /// Adds `bonus` to the score of `mov`, less and less as it nears `MAX_HISTORY`.
+    fn add(&mut self, color: Color, mov: Move, bonus: i32) {
+        let score = &mut self.scores[color as usize][mov.origin().index as usize]
+            [mov.target().index as usize];
+        *score += bonus - *score * bonus.abs() / MAX_HISTORY;
+    }
+}
+
+/// Returns `true` if `mov` neither captures nor promotes.
+pub fn is_quiet(mov: Move) -> bool {
+    !mov.is_capture() && mov.promotion().is_none()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    HashMove,
+    Captures,
+    Killers,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Hands out moves in order, see the module documentation.
+#[derive(Clone, Debug)]
+pub struct MovePicker {
+    stage: Stage,
+    /// Moves not picked yet, and not in the current stage.
+    moves: Vec<Move>,
+    /// Moves of the current stage not picked yet, with their score.
+    scored: Vec<(Move, i32)>,
+    hash_move: Option<Move>,
+    /// Killer moves followed by the countermove.
+    refutations: [Option<Move>; 3],
+    /// Captures and promotions put aside for the last stage.
+    bad_captures: Vec<Move>,
+    /// The last stage to pick moves from.
+    last: Stage,
+}
+
+impl MovePicker {
+    /// Returns a picker for `moves`, the legal moves of a position, with the moves of
+    /// `history` for `ply` after `previous` tried right after the captures.
+    pub fn new(
+        moves: Vec<Move>,
+        hash_move: Option<Move>,
+        history: &History,
+        ply: usize,
+        previous: Option<Move>,
+    ) -> Self {
+        let [first, second] = history.killers(ply);
+        Self {
+            stage: Stage::HashMove,
+            moves,
+            scored: Vec::new(),
+            hash_move,
+            refutations: [first, second, history.countermove(previous)],
+            bad_captures: Vec::new(),
+            last: Stage::BadCaptures,
+        }
+    }
+
+    /// Returns a picker for the captures and promotions among `moves` only, the losing ones
+    /// included.
+    pub fn captures(moves: Vec<Move>) -> Self {
+        Self {
+            stage: Stage::HashMove,
+            moves,
+            scored: Vec::new(),
+            hash_move: None,
+            refutations: [None; 3],
+            bad_captures: Vec::new(),
+            last: Stage::Captures,
+        }
+    }
+
+    /// Returns the next move to search, or `None` when every move was handed out.
+    pub fn next(&mut self, board: &Board, history: &History) -> Option<Move> {
+        loop {
+            if let Some(mov) = self.pick() {
+                return Some(mov);
+            }
+            if self.stage >= self.last {
+                self.stage = Stage::Done;
+                return None;
+            }
+            self.stage = match self.stage {
+                Stage::HashMove => Stage::Captures,
+                Stage::Captures => Stage::Killers,
+                Stage::Killers => Stage::Quiets,
+                Stage::Quiets => Stage::BadCaptures,
+                _ => Stage::Done,
+            };
+            self.score_stage(board, history);
+        }
+    }
+
+    /// Picks the best scored move of the current stage, or the hash move in its stage.
+    fn pick(&mut self) -> Option<Move> {
+        if self.stage == Stage::HashMove {
+            let mov = self.hash_move.take()?;
+            let index = self.moves.iter().position(|&legal| legal == mov)?;
+            return Some(self.moves.swap_remove(index));
+        }
+        let best = (0..self.scored.len()).max_by_key(|&i| self.scored[i].1)?;
+        Some(self.scored.swap_remove(best).0)
+    }
+
+    /// Moves the moves of the current stage from `moves` to `scored`.
+    fn score_stage(&mut self, board: &Board, history: &History) {
+        let color = board.color_to_move();
+        match self.stage {
+            Stage::Captures => {
+                let (noisy, quiet) = self.moves.drain(..).partition(|&mov| !is_quiet(mov));
+                self.moves = quiet;
+                for mov in noisy {
+                    // Losing moves are only put aside when there is a stage for them.
+                    if self.last > Stage::Captures && !board.see_ge(mov, 0) {
+                        self.bad_captures.push(mov);
+                    } else {
+                        self.scored.push((mov, mvv_lva(board, mov)));
+                    }
+                }
+            }
+            Stage::Killers => {
+                // Refutations come in order, the first found scores the highest.
+                for (i, refutation) in self.refutations.into_iter().enumerate() {
+                    let found = self.moves.iter().position(|&mov| Some(mov) == refutation);
+                    if let Some(index) = found {
+                        self.scored
+                            .push((self.moves.swap_remove(index), -(i as i32)));
+                    }
+                }
+            }
+            Stage::Quiets => {
+                self.scored = self
+                    .moves
+                    .drain(..)
+                    .map(|mov| (mov, history.score(color, mov)))
+                    .collect();
+            }
+            Stage::BadCaptures => {
+                self.scored = self
+                    .bad_captures
+                    .drain(..)
+                    .map(|mov| (mov, mvv_lva(board, mov)))
+                    .collect();
+            }
+            Stage::HashMove | Stage::Done => {}
+        }
+    }
+}
+
+/// Scores a capture or promotion by the value of the captured piece, then by the inverse
+/// value of the moving one. Queen promotions count as capturing a queen, other promotions
+/// come after every capture.
+fn mvv_lva(board: &Board, mov: Move) -> i32 {
+    let value = |piece_type| piece_type as i32;
+    let attacker = board
+        .at(mov.origin().index as usize)
+        .map_or(0, |piece| value(piece.piece_type()));
+    let victim = match mov.is_capture() {
+        // En passant captures leave the target square empty.
+        true => board
+            .at(mov.target().index as usize)
+            .map_or(value(PieceType::Pawn), |piece| value(piece.piece_type())),
+        false => 0,
+    };
+    let promotion = match mov.promotion() {
+        Some(PieceType::Queen) => value(PieceType::Queen),
+        Some(_) => -8,
+        None => 0,
+    };
+    // Captures of a pawn score above quiet promotions to a knight, bishop or rook.
+    (victim + promotion) * 8 - attacker + if mov.is_capture() { 64 } else { 0 }
+}
+
+#[test]
+fn picking_order() {
+    use crate::parser::load_position_from_fen;
+
+    let board = load_position_from_fen("r3k3/1P6/8/3q4/4P1n1/2N5/8/R3K3 w Qq - 0 1").unwrap();
+    let notation = |text| board.move_from_notation(text).unwrap();
+    let mut history = History::new();
+    history.update(
+        Color::White,
+        3,
+        None,
+        notation("a1a7"),
+        &[notation("c3b5")],
+        2,
+    );
+    history.update(Color::White, 5, None, notation("e1e2"), &[], 4);
+    let previous = Move::from_notation("h8h7").ok();
+    history.update(Color::White, 9, previous, notation("a1d1"), &[], 1);
+    assert_eq!(history.killers(3), [Some(notation("a1a7")), None]);
+    assert!(history.score(Color::White, notation("e1e2")) > 0);
+    assert!(history.score(Color::White, notation("c3b5")) < 0);
+
+    let mut picker = MovePicker::new(
+        board.get_legal_moves(),
+        Some(notation("e1f1")),
+        &history,
+        3,
+        previous,
+    );
+    let mut picked = Vec::new();
+    while let Some(mov) = picker.next(&board, &history) {
+        picked.push(mov.to_string());
+    }
+    assert_eq!(picked.len(), board.get_legal_moves().len());
+    let sorted = |moves: &[String]| {
+        let mut moves = moves.to_vec();
+        moves.sort();
+        moves
+    };
+    // Hash move, then captures by MVV-LVA with queen promotions among them
+    assert_eq!(picked[..5], ["e1f1", "b7a8q", "e4d5", "c3d5", "a1a8"]);
+    assert_eq!(sorted(&picked[5..8]), ["b7a8b", "b7a8n", "b7a8r"]);
+    // Killer, countermove, then the quiet moves by history
+    assert_eq!(picked[8..11], ["a1a7", "a1d1", "e1e2"]);
+    // Promotions the rook takes back come last
+    let bad = picked.len() - 4;
+    assert_eq!(picked[bad - 1], "c3b5");
+    assert_eq!(picked[bad], "b7b8q");
+    assert_eq!(sorted(&picked[bad + 1..]), ["b7b8b", "b7b8n", "b7b8r"]);
+
+    let mut picker = MovePicker::captures(board.get_legal_moves());
+    let mut captures = 0;
+    while let Some(mov) = picker.next(&board, &history) {
+        assert!(!is_quiet(mov));
+        captures += 1;
+    }
+    assert_eq!(captures, 11);
+}
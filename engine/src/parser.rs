@@ -2,89 +2,363 @@
 //!
 //! TODO: Implement PGN and possibly EPD
 
-use crate::bitboard::{Board, CastleRights};
+use crate::bitboard::{Board, CastleRights, Square};
+use crate::errors::{Error, FenError, FenErrorKind, FenField, Result};
 use crate::piece::{Color, Piece};
 
-pub const STARTING_POSITION_FEN: &'static str =
-    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+pub const STARTING_POSITION_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 /// Parses a string in [FEN](https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation)
 /// and returns a new instance of `bitboard::Board` that represents the position if the notation is valid.
-pub fn load_position_from_fen(fen: &str) -> Result<Board, String> {
-    let mut file: usize = 0;
-    let mut rank: usize = 7;
-    let mut pieces: [Option<Piece>; 64] = [None; 64];
-    let mut fen_fields = fen.split_ascii_whitespace();
+///
+/// Besides the syntax, the position itself is validated: each color must have exactly one king,
+/// pawns can't stand on the first or last rank, castling rights require the king and rook on
+/// their original squares, the side not to move can't be in check and the en passant square
+/// must be right behind a pawn that could have just moved two squares. The halfmove clock and
+/// fullmove number may be omitted, in which case they default to 0 and 1.
+pub fn load_position_from_fen(fen: &str) -> Result<Board> {
+    Ok(parse_fen(fen)?)
+}
 
-    for symbol in fen_fields
-        .next()
-        .ok_or(format!("Missing first field of FEN"))?
-        .chars()
-    {
-        if symbol == '/' {
-            file = 0;
-            rank -= 1;
-        } else if symbol.is_digit(10) {
-            file += symbol.to_digit(10).expect("Character is a digit") as usize;
-        } else {
-            let color = if symbol.is_uppercase() {
-                Color::White
-            } else {
-                Color::Black
-            };
-            pieces[rank * 8 + file] = match symbol.to_ascii_lowercase() {
-                'p' => Some(Piece::Pawn(color)),
-                'n' => Some(Piece::Knight(color)),
-                'b' => Some(Piece::Bishop(color)),
-                'r' => Some(Piece::Rook(color)),
-                'q' => Some(Piece::Queen(color)),
-                'k' => Some(Piece::King(color)),
-                _ => return Err(format!("Unrecognized symbol '{}'", symbol)),
-            };
-            file += 1;
-        }
-    }
+fn parse_fen(fen: &str) -> std::result::Result<Board, FenError> {
+    // Every field is ASCII when it's parsed successfully, so byte offsets of the fields are
+    // also character offsets.
+    let mut fen_fields = fen
+        .split_ascii_whitespace()
+        .map(|text| (text.as_ptr() as usize - fen.as_ptr() as usize, text));
+    let missing = |field| FenError {
+        field,
+        offset: fen.chars().count(),
+        kind: FenErrorKind::MissingField,
+    };
 
-    let color_to_move = fen_fields
-        .next()
-        .ok_or(format!("Missing second filed of FEN"))?;
-    let color_to_move = match color_to_move {
+    let (offset, placement) = fen_fields.next().ok_or(missing(FenField::PiecePlacement))?;
+    let pieces = parse_piece_placement(placement, offset)?;
+
+    let (offset, text) = fen_fields.next().ok_or(missing(FenField::ActiveColor))?;
+    let color_to_move = match text {
         "w" => Color::White,
         "b" => Color::Black,
         _ => {
-            return Err(format!(
-                "Unexpected symbol '{}' in second field of FEN",
-                color_to_move
-            ))
+            let (index, symbol) = text
+                .char_indices()
+                .find(|&(index, symbol)| index > 0 || !matches!(symbol, 'w' | 'b'))
+                .expect("Field is neither 'w' nor 'b'");
+            return Err(FenError {
+                field: FenField::ActiveColor,
+                offset: offset + index,
+                kind: FenErrorKind::UnexpectedSymbol(symbol),
+            });
         }
     };
+    let active_color_offset = offset;
 
-    let mut castling_rights = CastleRights::None;
-    for symbol in fen_fields
+    let (offset, text) = fen_fields.next().ok_or(missing(FenField::CastlingRights))?;
+    let castling_rights = parse_castling_rights(text, offset, &pieces)?;
+
+    let (offset, text) = fen_fields
         .next()
-        .ok_or(format!("Missing third field of FEN"))?
-        .chars()
-    {
+        .ok_or(missing(FenField::EnPassantSquare))?;
+    let en_passant_square = parse_en_passant_square(text, offset, &pieces, color_to_move)?;
+
+    let halfmove_clock = match fen_fields.next() {
+        None => 0,
+        Some((offset, text)) => text.parse().map_err(|_| FenError {
+            field: FenField::HalfmoveClock,
+            offset,
+            kind: FenErrorKind::InvalidNumber,
+        })?,
+    };
+
+    let fullmove_number = match fen_fields.next() {
+        None => 1,
+        Some((offset, text)) => match text.parse() {
+            Ok(number) if number > 0 => number,
+            _ => {
+                return Err(FenError {
+                    field: FenField::FullmoveNumber,
+                    offset,
+                    kind: FenErrorKind::InvalidNumber,
+                })
+            }
+        },
+    };
+
+    if let Some((offset, _)) = fen_fields.next() {
+        return Err(FenError {
+            field: FenField::FullmoveNumber,
+            offset,
+            kind: FenErrorKind::TooManyFields,
+        });
+    }
+
+    let board = Board::from_array(
+        &pieces,
+        castling_rights,
+        color_to_move,
+        en_passant_square,
+        halfmove_clock,
+        fullmove_number,
+    );
+    if board.is_king_attacked(!color_to_move) {
+        return Err(FenError {
+            field: FenField::ActiveColor,
+            offset: active_color_offset,
+            kind: FenErrorKind::OpponentInCheck,
+        });
+    }
+
+    Ok(board)
+}
+
+/// Parses the first field of a FEN record, found at `offset`.
+fn parse_piece_placement(
+    text: &str,
+    offset: usize,
+) -> std::result::Result<[Option<Piece>; 64], FenError> {
+    let error = |index, kind| FenError {
+        field: FenField::PiecePlacement,
+        offset: offset + index,
+        kind,
+    };
+    let mut file: usize = 0;
+    let mut rank: usize = 7;
+    let mut pieces: [Option<Piece>; 64] = [None; 64];
+    let mut after_digit = false;
+
+    for (index, symbol) in text.char_indices() {
         match symbol {
-            '-' => break,
-            'K' => castling_rights |= CastleRights::WhiteKS,
-            'Q' => castling_rights |= CastleRights::WhiteQS,
-            'k' => castling_rights |= CastleRights::BlackKS,
-            'q' => castling_rights |= CastleRights::BlackQS,
+            '/' => {
+                if file < 8 {
+                    return Err(error(index, FenErrorKind::TooFewFiles));
+                }
+                if rank == 0 {
+                    return Err(error(index, FenErrorKind::TooManyRanks));
+                }
+                file = 0;
+                rank -= 1;
+            }
+            // Two digits in a row are not allowed, the second one is an unexpected symbol.
+            '1'..='8' if !after_digit => {
+                file += symbol.to_digit(10).expect("Character is a digit") as usize;
+                if file > 8 {
+                    return Err(error(index, FenErrorKind::TooManyFiles));
+                }
+            }
             _ => {
-                return Err(format!(
-                    "Unexpected symbol '{}' in third field of FEN",
-                    symbol
-                ))
+                let color = if symbol.is_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                let piece = match symbol.to_ascii_lowercase() {
+                    'p' => Piece::Pawn(color),
+                    'n' => Piece::Knight(color),
+                    'b' => Piece::Bishop(color),
+                    'r' => Piece::Rook(color),
+                    'q' => Piece::Queen(color),
+                    'k' => Piece::King(color),
+                    _ => return Err(error(index, FenErrorKind::UnexpectedSymbol(symbol))),
+                };
+                if file == 8 {
+                    return Err(error(index, FenErrorKind::TooManyFiles));
+                }
+                if matches!(piece, Piece::Pawn(_)) && (rank == 0 || rank == 7) {
+                    return Err(error(index, FenErrorKind::PawnOnBackRank));
+                }
+                pieces[rank * 8 + file] = Some(piece);
+                file += 1;
             }
         }
+        after_digit = symbol.is_ascii_digit();
+    }
+
+    if file < 8 {
+        return Err(error(text.len(), FenErrorKind::TooFewFiles));
+    }
+    if rank > 0 {
+        return Err(error(text.len(), FenErrorKind::TooFewRanks));
+    }
+    for color in [Color::White, Color::Black] {
+        let kings = pieces
+            .iter()
+            .filter(|&&piece| piece == Some(Piece::King(color)))
+            .count();
+        if kings != 1 {
+            return Err(error(0, FenErrorKind::InvalidKingCount));
+        }
+    }
+
+    Ok(pieces)
+}
+
+/// Parses the third field of a FEN record, found at `offset`, checking that every castling
+/// right has its king and rook on their original squares.
+fn parse_castling_rights(
+    text: &str,
+    offset: usize,
+    pieces: &[Option<Piece>; 64],
+) -> std::result::Result<CastleRights, FenError> {
+    let error = |index, kind| FenError {
+        field: FenField::CastlingRights,
+        offset: offset + index,
+        kind,
+    };
+    if text == "-" {
+        return Ok(CastleRights::None);
+    }
+
+    let mut castling_rights = CastleRights::None;
+    for (index, symbol) in text.char_indices() {
+        let (flag, color, king, rook) = match symbol {
+            'K' => (CastleRights::WhiteKS, Color::White, 4, 7),
+            'Q' => (CastleRights::WhiteQS, Color::White, 4, 0),
+            'k' => (CastleRights::BlackKS, Color::Black, 60, 63),
+            'q' => (CastleRights::BlackQS, Color::Black, 60, 56),
+            _ => return Err(error(index, FenErrorKind::UnexpectedSymbol(symbol))),
+        };
+        if castling_rights.contains(flag) {
+            return Err(error(index, FenErrorKind::UnexpectedSymbol(symbol)));
+        }
+        if pieces[king] != Some(Piece::King(color)) || pieces[rook] != Some(Piece::Rook(color)) {
+            return Err(error(index, FenErrorKind::InconsistentCastlingRights));
+        }
+        castling_rights |= flag;
+    }
+
+    Ok(castling_rights)
+}
+
+/// Parses the fourth field of a FEN record, found at `offset`, checking that a pawn of the
+/// opponent of `color_to_move` could have just moved two squares past it.
+fn parse_en_passant_square(
+    text: &str,
+    offset: usize,
+    pieces: &[Option<Piece>; 64],
+    color_to_move: Color,
+) -> std::result::Result<Option<Square>, FenError> {
+    let error = |index, kind| FenError {
+        field: FenField::EnPassantSquare,
+        offset: offset + index,
+        kind,
+    };
+    if text == "-" {
+        return Ok(None);
+    }
+
+    let mut symbols = text.char_indices();
+    let file = match symbols.next() {
+        Some((_, symbol @ 'a'..='h')) => symbol as usize - 'a' as usize,
+        Some((index, symbol)) => return Err(error(index, FenErrorKind::UnexpectedSymbol(symbol))),
+        None => unreachable!("Fields are never empty"),
+    };
+    let rank = match symbols.next() {
+        Some((_, symbol @ '1'..='8')) => symbol as usize - '1' as usize,
+        Some((index, symbol)) => return Err(error(index, FenErrorKind::UnexpectedSymbol(symbol))),
+        None => return Err(error(0, FenErrorKind::InvalidEnPassantSquare)),
+    };
+    if let Some((index, symbol)) = symbols.next() {
+        return Err(error(index, FenErrorKind::UnexpectedSymbol(symbol)));
+    }
+
+    // The square is on the third rank of the side that just moved, checked first so the
+    // squares around it are on the board.
+    let expected_rank = match color_to_move {
+        Color::White => 5,
+        Color::Black => 2,
+    };
+    if rank != expected_rank {
+        return Err(error(0, FenErrorKind::InvalidEnPassantSquare));
+    }
+    // The pawn that moved stands in front of the en passant square, and both the square
+    // it came from and the one it passed over must be empty.
+    let (pawn, origin) = match color_to_move {
+        Color::White => (rank * 8 + file - 8, rank * 8 + file + 8),
+        Color::Black => (rank * 8 + file + 8, rank * 8 + file - 8),
+    };
+    if pieces[pawn] != Some(Piece::Pawn(!color_to_move))
+        || pieces[rank * 8 + file].is_some()
+        || pieces[origin].is_some()
+    {
+        return Err(error(0, FenErrorKind::InvalidEnPassantSquare));
+    }
+
+    Ok(Some(Square {
+        index: (rank * 8 + file) as u32,
+    }))
+}
+
+/// Returns the [FEN](https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation) string
+/// that describes the position represented by `board`, or an error if the board is not valid.
+pub fn store_position_as_fen(board: &Board) -> Result<String> {
+    if !board.is_valid() {
+        return Err(Error::InvalidBoard);
     }
 
-    Ok(Board::from_array(&pieces, castling_rights, color_to_move))
+    Ok(fen_string(board))
 }
 
-pub fn store_position_as_fen(_board: &Board) -> Result<String, String> {
-    todo!();
+/// Builds the FEN string of `board` without checking whether it's valid.
+pub(crate) fn fen_string(board: &Board) -> String {
+    let mut fen = String::with_capacity(90);
+
+    for rank in (0..8).rev() {
+        let mut empty_squares = 0;
+        for file in 0..8 {
+            match board.at(rank * 8 + file) {
+                Some(piece) => {
+                    if empty_squares > 0 {
+                        fen.push_str(&empty_squares.to_string());
+                        empty_squares = 0;
+                    }
+                    fen.push_str(&piece.to_string());
+                }
+                None => empty_squares += 1,
+            }
+        }
+        if empty_squares > 0 {
+            fen.push_str(&empty_squares.to_string());
+        }
+        if rank > 0 {
+            fen.push('/');
+        }
+    }
+
+    fen.push(' ');
+    fen.push(match board.color_to_move() {
+        Color::White => 'w',
+        Color::Black => 'b',
+    });
+
+    fen.push(' ');
+    let castling_rights = board.castling_rights();
+    if castling_rights.is_empty() {
+        fen.push('-');
+    }
+    for (flag, symbol) in [
+        (CastleRights::WhiteKS, 'K'),
+        (CastleRights::WhiteQS, 'Q'),
+        (CastleRights::BlackKS, 'k'),
+        (CastleRights::BlackQS, 'q'),
+    ] {
+        if castling_rights.contains(flag) {
+            fen.push(symbol);
+        }
+    }
+
+    fen.push(' ');
+    match board.en_passant_square() {
+        Some(square) => fen.push_str(&square.to_string()),
+        None => fen.push('-'),
+    }
+
+    fen.push_str(&format!(
+        " {} {}",
+        board.halfmove_clock(),
+        board.fullmove_number()
+    ));
+
+    fen
 }
 
 #[test]
@@ -96,10 +370,137 @@ fn fen_parser_load() {
 }
 
 #[test]
-#[ignore = "function not implemented yet"]
 fn fen_parser_store() {
     assert_eq!(
         store_position_as_fen(&Board::new()).unwrap(),
         STARTING_POSITION_FEN,
     );
 }
+
+#[test]
+fn fen_parser_round_trip() {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    let corpus = [
+        STARTING_POSITION_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "8/8/8/8/8/8/8/K6k b - - 99 120",
+    ];
+
+    // Every position reached by playing random games from the corpus must survive a
+    // round trip through FEN.
+    let mut rng = StdRng::seed_from_u64(0);
+    for fen in corpus {
+        assert_eq!(
+            store_position_as_fen(&load_position_from_fen(fen).unwrap()).unwrap(),
+            fen
+        );
+        let mut board = load_position_from_fen(fen).unwrap();
+        for _ in 0..100 {
+            let Some(&mov) = board.get_legal_moves().choose(&mut rng) else {
+                break;
+            };
+            board.make_move(mov);
+            let fen = store_position_as_fen(&board).unwrap();
+            assert_eq!(load_position_from_fen(&fen).unwrap(), board);
+            assert_eq!(
+                store_position_as_fen(&board.to_string().parse().unwrap()).unwrap(),
+                fen
+            );
+        }
+    }
+}
+
+#[test]
+fn fen_parser_rejects_malformed() {
+    let error = |fen| parse_fen(fen).unwrap_err();
+    let malformed = [
+        "",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR/8 w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+        "rnbqkbnrr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/44/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkqK - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNé w KQkq - 0 1",
+    ];
+    for fen in malformed {
+        assert!(load_position_from_fen(fen).is_err(), "{}", fen);
+    }
+
+    assert_eq!(
+        error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR/8 w KQkq - 0 1"),
+        FenError {
+            field: FenField::PiecePlacement,
+            offset: 43,
+            kind: FenErrorKind::TooManyRanks,
+        }
+    );
+    assert_eq!(
+        error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1"),
+        FenError {
+            field: FenField::EnPassantSquare,
+            offset: 52,
+            kind: FenErrorKind::UnexpectedSymbol('9'),
+        }
+    );
+}
+
+#[test]
+fn fen_parser_rejects_impossible_positions() {
+    let kind = |fen| parse_fen(fen).unwrap_err().kind;
+    assert_eq!(
+        kind("4k3/8/8/8/8/8/8/8 w - - 0 1"),
+        FenErrorKind::InvalidKingCount
+    );
+    assert_eq!(
+        kind("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+        FenErrorKind::InvalidKingCount
+    );
+    assert_eq!(
+        kind("4k2P/8/8/8/8/8/8/4K3 w - - 0 1"),
+        FenErrorKind::PawnOnBackRank
+    );
+    assert_eq!(
+        kind("4k3/8/8/8/8/8/8/4K2R w Q - 0 1"),
+        FenErrorKind::InconsistentCastlingRights
+    );
+    assert_eq!(
+        kind("4k3/8/8/8/8/8/8/3K3R w K - 0 1"),
+        FenErrorKind::InconsistentCastlingRights
+    );
+    assert_eq!(
+        kind("4k2R/8/8/8/8/8/8/4K3 w - - 0 1"),
+        FenErrorKind::OpponentInCheck
+    );
+    assert_eq!(
+        kind("4k3/8/8/3pP3/8/8/8/4K3 w - e6 0 1"),
+        FenErrorKind::InvalidEnPassantSquare
+    );
+    assert_eq!(
+        kind("4k3/8/8/3pP3/8/8/8/4K3 b - d6 0 1"),
+        FenErrorKind::InvalidEnPassantSquare
+    );
+    // Squares on the edge ranks have no square in front of or behind them
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq a1 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq h1 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq a8 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq h8 0 1",
+    ] {
+        assert_eq!(kind(fen), FenErrorKind::InvalidEnPassantSquare, "{}", fen);
+    }
+    assert!(load_position_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").is_ok());
+}
@@ -0,0 +1,147 @@
+//! Move path enumeration, used to verify the move generator.
+//!
+//! `perft` counts the leaf nodes of the tree of legal moves up to a given depth. Comparing
+//! the counts against known results is the standard way of catching move generation bugs,
+//! and `perft_divide` helps to narrow them down by splitting the count by root move.
+
+use crate::bitboard::{Board, Move};
+
+impl Board {
+    /// Returns the number of leaf nodes of the tree of legal moves `depth` plies deep.
+    pub fn perft(&self, depth: u32) -> u64 {
+        perft(&mut self.clone(), depth)
+    }
+
+    /// Returns the number of leaf nodes `depth` plies deep reached after each legal move.
+    ///
+    /// The counts add up to `perft(depth)`. Depths below 1 return no moves.
+    pub fn perft_divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        let mut board = self.clone();
+        self.get_legal_moves()
+            .into_iter()
+            .map(|mov| {
+                let undo = board.make_move(mov);
+                let nodes = perft(&mut board, depth - 1);
+                board.undo_move(mov, undo);
+                (mov, nodes)
+            })
+            .collect()
+    }
+}
+
+fn perft(board: &mut Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = board.get_legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0;
+    for mov in moves {
+        let undo = board.make_move(mov);
+        nodes += perft(board, depth - 1);
+        board.undo_move(mov, undo);
+    }
+    nodes
+}
+
+/// Positions with their known node count at a given depth, from the
+/// [Chess Programming Wiki](https://www.chessprogramming.org/Perft_Results) and the perft
+/// suite collected by Martin Sedlak for en passant, castling and promotion edge cases.
+#[cfg(test)]
+const PERFT_SUITE: &[(&str, u32, u64)] = &[
+    (crate::parser::STARTING_POSITION_FEN, 4, 197281),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        3,
+        97862,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, 674624),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        4,
+        422333,
+    ),
+    (
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        4,
+        422333,
+    ),
+    (
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        3,
+        62379,
+    ),
+    (
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        3,
+        89890,
+    ),
+    // En passant
+    ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6, 1134888),
+    ("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6, 1015133),
+    ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 6, 1440467),
+    // Castling
+    ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", 6, 661072),
+    ("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", 6, 803711),
+    ("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 4, 1274206),
+    ("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", 4, 1720476),
+    // Promotions
+    ("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 6, 3821001),
+    ("4k3/1P6/8/8/8/8/K7/8 w - - 0 1", 6, 217342),
+    ("8/P1k5/K7/8/8/8/8/8 w - - 0 1", 6, 92683),
+    // Checks, checkmates and stalemates
+    ("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1", 5, 1004658),
+    ("K1k5/8/P7/8/8/8/8/8 w - - 0 1", 6, 2217),
+    ("8/k1P5/8/1K6/8/8/8/8 w - - 0 1", 7, 567584),
+    ("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1", 4, 23527),
+];
+
+#[test]
+fn perft_suite() {
+    for &(fen, depth, nodes) in PERFT_SUITE {
+        let board = crate::parser::load_position_from_fen(fen).unwrap();
+        assert_eq!(board.perft(depth), nodes, "{}", fen);
+    }
+}
+
+#[test]
+fn perft_divide_adds_up() {
+    let board = Board::new();
+    let divide = board.perft_divide(3);
+    assert_eq!(divide.len(), 20);
+    assert_eq!(divide.iter().map(|&(_, nodes)| nodes).sum::<u64>(), 8902);
+    assert!(board.perft_divide(0).is_empty());
+}
+
+#[test]
+#[ignore = "takes too long without optimizations"]
+fn perft_suite_deep() {
+    let positions = [
+        (crate::parser::STARTING_POSITION_FEN, 5, 4865609),
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            4,
+            4085603,
+        ),
+        (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            4,
+            2103487,
+        ),
+        (
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            4,
+            3894594,
+        ),
+    ];
+    for (fen, depth, nodes) in positions {
+        let board = crate::parser::load_position_from_fen(fen).unwrap();
+        assert_eq!(board.perft(depth), nodes, "{}", fen);
+    }
+}
@@ -1,14 +1,26 @@
 //! Useful enums for distinguishing chess pieces.
 
 use std::fmt;
+use std::ops::Not;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Color {
     White,
     Black,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Not for Color {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Piece {
     Pawn(Color),
     Knight(Color),
@@ -18,12 +30,56 @@ pub enum Piece {
     King(Color),
 }
 
+/// The kind of a chess piece, regardless of its color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
 impl Piece {
+    /// Constructs a `Piece` of the given type and color.
+    pub fn new(piece_type: PieceType, color: Color) -> Self {
+        match piece_type {
+            PieceType::Pawn => Piece::Pawn(color),
+            PieceType::Knight => Piece::Knight(color),
+            PieceType::Bishop => Piece::Bishop(color),
+            PieceType::Rook => Piece::Rook(color),
+            PieceType::Queen => Piece::Queen(color),
+            PieceType::King => Piece::King(color),
+        }
+    }
+
+    /// Returns the type of the piece.
+    pub fn piece_type(self) -> PieceType {
+        match self {
+            Piece::Pawn(_) => PieceType::Pawn,
+            Piece::Knight(_) => PieceType::Knight,
+            Piece::Bishop(_) => PieceType::Bishop,
+            Piece::Rook(_) => PieceType::Rook,
+            Piece::Queen(_) => PieceType::Queen,
+            Piece::King(_) => PieceType::King,
+        }
+    }
+
     /// Returns `true` if the piece is a slider, meaning its moves have _infinite_ range.
     pub fn is_slider(self) -> bool {
+        matches!(self, Piece::Bishop(_) | Piece::Rook(_) | Piece::Queen(_))
+    }
+
+    /// Returns the color of the piece.
+    pub fn color(self) -> Color {
         match self {
-            Piece::Bishop(_) | Piece::Rook(_) | Piece::Queen(_) => true,
-            _ => false,
+            Piece::Pawn(c)
+            | Piece::Knight(c)
+            | Piece::Bishop(c)
+            | Piece::Rook(c)
+            | Piece::Queen(c)
+            | Piece::King(c) => c,
         }
     }
 }
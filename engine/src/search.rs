@@ -0,0 +1,1224 @@
+//! Best move search.
+//!
+//! `Searcher` runs a negamax alpha-beta search with iterative deepening: the position is
+//! searched one ply deeper at a time until a limit is reached, and the principal variation
+//! of each iteration is tried first in the next one. At the horizon, a quiescence search
+//! keeps resolving captures so that positions are only evaluated once they are quiet.
+//!
+//! Results are kept in a transposition table shared by every search of the same
+//! `Searcher`. Moves after the first one of a node are searched with a null window, just
+//! enough to prove they are worse, and only searched again with the full window when they
+//! turn out better. Stored results cut the search short in those null window nodes, which
+//! keeps the principal variation complete.
+//!
+//! On top of that, the search spends less effort on the moves least likely to matter,
+//! every technique being switchable through `SearchOptions`:
+//!
+//! - null move pruning: if passing the turn still leaves the side to move above beta in a
+//!   reduced search, a real move will too, except in zugzwang. The side to move must have
+//!   pieces besides pawns, and null moves are never played twice in a row,
+//! - late move reductions: quiet moves ordered late are searched less deep, and only
+//!   searched again at full depth when they turn out better than alpha,
+//! - reverse futility pruning: near the horizon, a static evaluation far enough above beta
+//!   is trusted without searching,
+//! - futility pruning: near the horizon, quiet moves aren't searched when the static
+//!   evaluation is too far below alpha for them to catch up,
+//! - razoring: even further below alpha, a node is resolved by a quiescence search,
+//! - check extensions: positions in check are searched one ply deeper,
+//! - aspiration windows: iterations start with a narrow window around the score of the
+//!   previous one, widened when the score falls outside of it.
+//!
+//! In MultiPV mode, every iteration searches the root once per line asked for, each time
+//! leaving out the first moves of the lines already found, so that the lines start with
+//! distinct moves.
+//!
+//! With tablebases, the root moves are limited to those that keep the best outcome the
+//! tables give, and positions reached by a capture or a pawn move that the tables cover are
+//! scored by their WDL outcome.
+//!
+//! Scores are in centipawns from the point of view of the side to move. Mates are scored
+//! as `MATE` minus the number of plies to the mate, see `mate_in`.
+
+use crate::bitboard::{Board, Move};
+use crate::eval::evaluate;
+use crate::game::Game;
+use crate::movepick::{is_quiet, History, MovePicker};
+use crate::piece::Piece;
+use crate::tablebase::{Tablebases, Wdl};
+use crate::time::{Clock, TimeManager};
+use crate::transposition::{Bound, Entry, TranspositionTable};
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Score of a checkmate delivered on the current move.
+pub const MATE: i32 = 32_000;
+/// Bound above any score, used as the initial search window.
+pub const INFINITY: i32 = MATE + 1;
+/// Maximum depth of the search tree, quiescence search included.
+pub const MAX_PLY: usize = 128;
+/// Score of a position the tablebases tell is won, minus the plies to it. It's below any
+/// mate score since the mate may be far away.
+pub const TB_WIN: i32 = MATE - 2 * MAX_PLY as i32;
+/// Lowest score of a won position whose distance to the win or the mate is counted in plies
+/// from the root.
+const DISTANCE_SCORE: i32 = TB_WIN - MAX_PLY as i32;
+
+/// Maximum number of search threads.
+pub const MAX_THREADS: usize = 256;
+/// Maximum number of lines searched in MultiPV mode.
+pub const MAX_MULTI_PV: usize = 256;
+
+/// How often, in nodes, the clock and the stop flag are checked.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+/// Half width of the first aspiration window, doubled on every failure.
+const ASPIRATION_WINDOW: i32 = 25;
+/// First iteration searched with an aspiration window.
+const ASPIRATION_DEPTH: i32 = 5;
+/// Minimum depth of null move pruning.
+const NULL_MOVE_DEPTH: i32 = 3;
+/// Maximum depth and margin per ply of reverse futility pruning.
+const REVERSE_FUTILITY_DEPTH: i32 = 6;
+const REVERSE_FUTILITY_MARGIN: i32 = 80;
+/// Maximum depth and margin per ply of futility pruning.
+const FUTILITY_DEPTH: i32 = 3;
+const FUTILITY_MARGIN: i32 = 150;
+/// Maximum depth and margin per ply of razoring.
+const RAZORING_DEPTH: i32 = 2;
+const RAZORING_MARGIN: i32 = 300;
+/// Minimum depth of late move reductions, and number of moves searched before them.
+const LATE_MOVE_DEPTH: i32 = 3;
+const LATE_MOVES: usize = 3;
+
+/// Returns the number of moves to a mate if `score` is a mate score, positive when the side
+/// to move mates and negative when it gets mated.
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score > MATE - MAX_PLY as i32 {
+        Some((MATE - score + 1) / 2)
+    } else if score < -MATE + MAX_PLY as i32 {
+        Some(-(MATE + score) / 2)
+    } else {
+        None
+    }
+}
+
+/// Conditions that stop a search. A search without any limit only stops at `MAX_PLY`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum depth, in plies, of the iterative deepening.
+    pub depth: Option<u32>,
+    /// Maximum number of nodes to visit.
+    pub nodes: Option<u64>,
+    /// Maximum time to search for.
+    pub time: Option<Duration>,
+    /// The clocks of the game, to let a `TimeManager` decide how long to search for.
+    pub clock: Option<Clock>,
+}
+
+/// Search techniques to use, see the module documentation. All of them are on by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility: bool,
+    pub futility: bool,
+    pub razoring: bool,
+    pub check_extensions: bool,
+    pub aspiration_windows: bool,
+}
+
+impl SearchOptions {
+    /// Every technique.
+    pub const ALL: Self = Self {
+        null_move: true,
+        late_move_reductions: true,
+        reverse_futility: true,
+        futility: true,
+        razoring: true,
+        check_extensions: true,
+        aspiration_windows: true,
+    };
+    /// A plain alpha-beta search.
+    pub const NONE: Self = Self {
+        null_move: false,
+        late_move_reductions: false,
+        reverse_futility: false,
+        futility: false,
+        razoring: false,
+        check_extensions: false,
+        aspiration_windows: false,
+    };
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// A line found by the search, with its score.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Line {
+    /// Score of the position for the side to move, if the line is played.
+    pub score: i32,
+    pub moves: Vec<Move>,
+}
+
+/// The result of the last completed iteration of a search.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchResult {
+    /// The best move found, or `None` if there are no legal moves.
+    pub best_move: Option<Move>,
+    /// Score of the position for the side to move.
+    pub score: i32,
+    /// The principal variation, starting with `best_move`.
+    pub pv: Vec<Move>,
+    /// The best lines found, as many as `Searcher::multi_pv` and the legal moves allow,
+    /// from the best one, which is the principal variation, to the worst.
+    pub lines: Vec<Line>,
+    /// Depth of the last completed iteration.
+    pub depth: u32,
+    /// Nodes visited during the whole search.
+    pub nodes: u64,
+    /// Time spent on the whole search.
+    pub time: Duration,
+}
+
+/// Converts a mate or tablebase score relative to the root into one relative to the node at
+/// `ply`, as stored in the transposition table.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        score if score >= DISTANCE_SCORE => score + ply as i32,
+        score if score <= -DISTANCE_SCORE => score - ply as i32,
+        score => score,
+    }
+}
+
+/// Converts a score read from the transposition table back into one relative to the root.
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        score if score >= DISTANCE_SCORE => score - ply as i32,
+        score if score <= -DISTANCE_SCORE => score + ply as i32,
+        score => score,
+    }
+}
+
+/// Returns how many plies less the `moves`-th move of a node is searched at `depth`.
+fn late_move_reduction(depth: i32, moves: usize) -> i32 {
+    (0.75 + (depth as f64).ln() * (moves as f64).ln() / 2.25) as i32
+}
+
+/// Returns `true` if the side to move has pieces other than pawns and its king, without
+/// which null move pruning is unsafe: most zugzwangs happen in pawn endgames.
+fn has_non_pawn_material(board: &Board) -> bool {
+    let color = board.color_to_move();
+    let pawns_and_king = board.pieces(Piece::Pawn(color)) | board.pieces(Piece::King(color));
+    board.color_occupancy(color) & !pawns_and_king != 0
+}
+
+/// Searches positions for their best move.
+///
+/// The search runs on `threads` threads (Lazy SMP): they all search the same position and
+/// only share the transposition table, so each one benefits from the results of the others.
+/// Helper threads start every other iteration one ply deeper than the main one so that
+/// they explore different parts of the tree. The result is that of the main thread, which
+/// alone applies the limits and stops the helpers when it's done. With a single thread, the
+/// search is deterministic.
+#[derive(Debug)]
+pub struct Searcher {
+    tt: TranspositionTable,
+    options: SearchOptions,
+    /// Set from outside to stop the search.
+    stop: Arc<AtomicBool>,
+    /// History of each thread, the main one first, kept from one search to the next.
+    histories: Vec<History>,
+    multi_pv: usize,
+    tablebases: Option<Tablebases>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Searcher {
+    /// Returns a new searcher, running on a single thread.
+    pub fn new() -> Self {
+        Self {
+            tt: TranspositionTable::default(),
+            options: SearchOptions::default(),
+            stop: Arc::new(AtomicBool::new(false)),
+            histories: vec![History::new()],
+            multi_pv: 1,
+            tablebases: None,
+        }
+    }
+
+    /// Returns the transposition table.
+    pub fn tt(&self) -> &TranspositionTable {
+        &self.tt
+    }
+
+    /// Returns the transposition table, to resize it.
+    pub fn tt_mut(&mut self) -> &mut TranspositionTable {
+        &mut self.tt
+    }
+
+    /// Returns the search techniques in use.
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    /// Returns the search techniques in use, to turn some of them on or off.
+    pub fn options_mut(&mut self) -> &mut SearchOptions {
+        &mut self.options
+    }
+
+    /// Returns the number of threads searches run on.
+    pub fn threads(&self) -> usize {
+        self.histories.len()
+    }
+
+    /// Sets the number of threads searches run on, between 1 and `MAX_THREADS`.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.histories
+            .resize_with(threads.clamp(1, MAX_THREADS), History::new);
+    }
+
+    /// Forgets everything learnt from previous searches, for a new game.
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        for history in &mut self.histories {
+            history.clear();
+        }
+    }
+
+    /// Returns the number of lines searched, see `SearchResult::lines`.
+    pub fn multi_pv(&self) -> usize {
+        self.multi_pv
+    }
+
+    /// Sets the number of lines searched, between 1 and `MAX_MULTI_PV`.
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.multi_pv = lines.clamp(1, MAX_MULTI_PV);
+    }
+
+    /// Returns the tablebases the search probes, if any.
+    pub fn tablebases(&self) -> Option<&Tablebases> {
+        self.tablebases.as_ref()
+    }
+
+    /// Sets the tablebases the search probes, `None` to stop probing.
+    pub fn set_tablebases(&mut self, tablebases: Option<Tablebases>) {
+        self.tablebases = tablebases;
+    }
+
+    /// Returns the flag that stops the search when set, from any thread.
+    ///
+    /// The searcher never clears the flag, so it stops every later search until it's reset.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Searches `board` for its best move within `limits`.
+    pub fn search(&mut self, board: &Board, limits: &Limits) -> SearchResult {
+        self.search_game(&Game::new(board.clone()), limits)
+    }
+
+    /// Searches the current position of `game` for its best move within `limits`, scoring
+    /// repetitions of earlier positions of the game as draws.
+    pub fn search_game(&mut self, game: &Game, limits: &Limits) -> SearchResult {
+        self.search_game_with(game, limits, |_| {})
+    }
+
+    /// Searches like `search_game`, calling `on_iteration` with the result of every
+    /// completed iteration. Node counts are those of all the threads together.
+    pub fn search_game_with(
+        &mut self,
+        game: &Game,
+        limits: &Limits,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let start = Instant::now();
+        let mut limits = *limits;
+        let mut time_manager = limits
+            .clock
+            .and_then(|clock| TimeManager::new(&clock, game.board().color_to_move()));
+        if let Some(time_manager) = &time_manager {
+            let hard_limit = time_manager.hard_limit();
+            limits.time = Some(limits.time.map_or(hard_limit, |time| time.min(hard_limit)));
+        }
+        self.tt.new_search();
+        for history in &mut self.histories {
+            history.age();
+        }
+
+        let mut board = game.board().clone();
+        let moves = board.get_legal_moves();
+        if moves.is_empty() {
+            return SearchResult {
+                score: if board.in_check() { -MATE } else { 0 },
+                ..Default::default()
+            };
+        }
+        // Root moves the tablebases tell are worse than others are left out.
+        let best_moves = self
+            .tablebases
+            .as_ref()
+            .and_then(|tablebases| tablebases.root_moves(&board))
+            .unwrap_or_else(|| moves.clone());
+        let tablebase_excluded: Vec<_> = moves
+            .iter()
+            .copied()
+            .filter(|mov| !best_moves.contains(mov))
+            .collect();
+        let mut result = SearchResult {
+            best_move: best_moves.first().copied(),
+            ..Default::default()
+        };
+
+        let multi_pv = self.multi_pv.min(best_moves.len());
+        let total_nodes = AtomicU64::new(0);
+        let done = AtomicBool::new(false);
+        let (main_history, helper_histories) = self
+            .histories
+            .split_first_mut()
+            .expect("There is at least one thread");
+        let shared = Shared {
+            tt: &self.tt,
+            options: self.options,
+            start,
+            stop: &self.stop,
+            done: &done,
+            total_nodes: &total_nodes,
+            tablebases: self.tablebases.as_ref(),
+        };
+        thread::scope(|scope| {
+            for (id, history) in helper_histories.iter_mut().enumerate() {
+                // Helpers don't stop after a given depth, only with the main thread or when
+                // the nodes or time run out.
+                let helper_limits = Limits {
+                    nodes: limits.nodes,
+                    time: limits.time,
+                    ..Default::default()
+                };
+                let mut worker = Worker::new(shared, helper_limits, game, history);
+                worker.excluded.clone_from(&tablebase_excluded);
+                let first_depth = 1 + (id as i32 + 1) % 2;
+                scope.spawn(move || worker.help(game.board().clone(), first_depth));
+            }
+
+            let mut worker = Worker::new(shared, limits, game, main_history);
+            let max_depth = limits.depth.unwrap_or(MAX_PLY as u32).min(MAX_PLY as u32);
+            for depth in 1..=max_depth {
+                let mut lines = Vec::with_capacity(multi_pv);
+                worker.excluded.clone_from(&tablebase_excluded);
+                for i in 0..multi_pv {
+                    // Each line starts from the line of the same rank in the last iteration.
+                    let (previous, pv) = result
+                        .lines
+                        .get(i)
+                        .map_or((0, &[][..]), |line| (line.score, &line.moves[..]));
+                    let score = worker.search_root(&mut board, depth as i32, previous, pv);
+                    if worker.stopped {
+                        break;
+                    }
+                    worker.excluded.extend(worker.pv[0].first());
+                    lines.push(Line {
+                        score,
+                        moves: worker.pv[0].clone(),
+                    });
+                }
+                // The result of an interrupted iteration is incomplete.
+                if worker.stopped {
+                    break;
+                }
+                lines.sort_by_key(|line| Reverse(line.score));
+                let score = lines[0].score;
+                result.best_move = lines[0].moves.first().copied();
+                result.pv = lines[0].moves.clone();
+                result.score = score;
+                result.lines = lines;
+                result.depth = depth;
+                result.nodes = worker.total_nodes();
+                result.time = start.elapsed();
+                on_iteration(&result);
+                if let Some(time_manager) = &mut time_manager {
+                    time_manager.update(result.best_move, score);
+                    // A forced move is played without wasting any time on it.
+                    if best_moves.len() == 1 || time_manager.should_stop(result.time) {
+                        break;
+                    }
+                }
+                if mate_in(score).is_some_and(|mate| mate.unsigned_abs() <= depth) {
+                    break;
+                }
+            }
+            worker.flush_nodes();
+            done.store(true, Ordering::Relaxed);
+        });
+        result.nodes = total_nodes.load(Ordering::Relaxed);
+        result.time = start.elapsed();
+        result
+    }
+}
+
+/// What the threads of a search share.
+#[derive(Clone, Copy, Debug)]
+struct Shared<'a> {
+    tt: &'a TranspositionTable,
+    options: SearchOptions,
+    start: Instant,
+    stop: &'a AtomicBool,
+    /// Set by the main thread once it's done, to stop the helpers.
+    done: &'a AtomicBool,
+    /// Nodes visited by all the threads, updated every `TIME_CHECK_INTERVAL` nodes.
+    total_nodes: &'a AtomicU64,
+    tablebases: Option<&'a Tablebases>,
+}
+
+/// The state of one thread of a search.
+#[derive(Debug)]
+struct Worker<'a> {
+    shared: Shared<'a>,
+    limits: Limits,
+    /// Nodes visited by the thread, and how many of them were added to the total.
+    nodes: u64,
+    flushed_nodes: u64,
+    stopped: bool,
+    /// Hashes of the positions leading to the one being searched, for repetitions.
+    hashes: Vec<u64>,
+    /// Moves leading to the position being searched, `None` for null moves.
+    played: Vec<Option<Move>>,
+    history: &'a mut History,
+    /// Root moves left out of the search, by the tablebases or in MultiPV mode.
+    excluded: Vec<Move>,
+    /// Triangular table of principal variations, one per ply.
+    pv: Vec<Vec<Move>>,
+}
+
+impl<'a> Worker<'a> {
+    fn new(shared: Shared<'a>, limits: Limits, game: &Game, history: &'a mut History) -> Self {
+        let mut hashes = Vec::with_capacity(game.hashes().len() + MAX_PLY);
+        hashes.extend_from_slice(game.hashes());
+        let mut played = Vec::with_capacity(game.moves().len() + MAX_PLY);
+        played.extend(game.moves().iter().copied().map(Some));
+        Self {
+            shared,
+            limits,
+            nodes: 0,
+            flushed_nodes: 0,
+            stopped: false,
+            hashes,
+            played,
+            history,
+            excluded: Vec::new(),
+            pv: (0..=MAX_PLY).map(|_| Vec::with_capacity(MAX_PLY)).collect(),
+        }
+    }
+
+    /// Runs the iterative deepening of a helper thread from `first_depth`, until the main
+    /// thread is done.
+    fn help(&mut self, mut board: Board, first_depth: i32) {
+        let (mut score, mut pv) = (0, Vec::new());
+        for depth in first_depth..=MAX_PLY as i32 {
+            score = self.search_root(&mut board, depth, score, &pv);
+            if self.stopped {
+                break;
+            }
+            pv.clone_from(&self.pv[0]);
+        }
+        self.flush_nodes();
+    }
+
+    /// Returns the number of nodes visited by all the threads so far, counting those of
+    /// the other threads up to their last update.
+    fn total_nodes(&self) -> u64 {
+        self.shared.total_nodes.load(Ordering::Relaxed) + self.nodes - self.flushed_nodes
+    }
+
+    /// Adds the nodes visited since the last call to the total.
+    fn flush_nodes(&mut self) {
+        self.shared
+            .total_nodes
+            .fetch_add(self.nodes - self.flushed_nodes, Ordering::Relaxed);
+        self.flushed_nodes = self.nodes;
+    }
+
+    /// Searches the root at `depth`, within an aspiration window around `previous`, the
+    /// score of the last iteration.
+    fn search_root(&mut self, board: &mut Board, depth: i32, previous: i32, pv: &[Move]) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = (-INFINITY, INFINITY);
+        if self.shared.options.aspiration_windows
+            && depth >= ASPIRATION_DEPTH
+            && mate_in(previous).is_none()
+        {
+            alpha = previous - delta;
+            beta = previous + delta;
+        }
+        loop {
+            let score = self.negamax(board, depth, 0, alpha, beta, pv);
+            if self.stopped {
+                return score;
+            }
+            if score <= alpha {
+                beta = (alpha + beta) / 2;
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta *= 2;
+        }
+    }
+
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        mut depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        pv: &[Move],
+    ) -> i32 {
+        self.pv[ply].clear();
+        if ply > 0 && self.is_draw(board) {
+            return 0;
+        }
+        let in_check = board.in_check();
+        if in_check && self.shared.options.check_extensions {
+            depth += 1;
+        }
+        if depth <= 0 || ply >= MAX_PLY {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+        self.visit();
+        if self.stopped {
+            return 0;
+        }
+
+        // Nodes searched with a null window only need to tell if the score is above alpha.
+        let pv_node = beta - alpha > 1;
+        let entry = self.shared.tt.probe(board.hash());
+        if let Some(entry) = entry.filter(|entry| !pv_node && entry.depth as i32 >= depth) {
+            let score = score_from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+
+        // After a capture or a pawn move, the tablebases give the outcome of the position,
+        // taken as the score when it's a draw or falls outside of the window.
+        if let Some(tablebases) = self.shared.tablebases {
+            if ply > 0 && board.halfmove_clock() == 0 && tablebases.covers(board) {
+                let (win, loss) = (TB_WIN - ply as i32, -TB_WIN + ply as i32);
+                match tablebases.probe_wdl(board) {
+                    Some(Wdl::Win) if win >= beta => return win,
+                    Some(Wdl::Loss) if loss <= alpha => return loss,
+                    Some(Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss) => return 0,
+                    _ => {}
+                }
+            }
+        }
+
+        let prunable = !pv_node && !in_check;
+        let eval = if in_check { -INFINITY } else { evaluate(board) };
+        if prunable && mate_in(beta).is_none() {
+            if self.shared.options.reverse_futility
+                && depth <= REVERSE_FUTILITY_DEPTH
+                && eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+            {
+                return eval;
+            }
+            if self.shared.options.razoring
+                && depth <= RAZORING_DEPTH
+                && eval + RAZORING_MARGIN * depth < alpha
+            {
+                let score = self.quiescence(board, ply, alpha, beta);
+                if score < alpha || self.stopped {
+                    return score;
+                }
+            }
+            if self.shared.options.null_move
+                && depth >= NULL_MOVE_DEPTH
+                && eval >= beta
+                && self.played.last() != Some(&None)
+                && has_non_pawn_material(board)
+            {
+                let reduction = 3 + depth / 6;
+                self.hashes.push(board.hash());
+                self.played.push(None);
+                let undo = board.make_null_move();
+                let score =
+                    -self.negamax(board, depth - 1 - reduction, ply + 1, -beta, -beta + 1, &[]);
+                board.undo_null_move(undo);
+                self.played.pop();
+                self.hashes.pop();
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    // Mates found after passing the turn aren't proven.
+                    return if mate_in(score).is_some() {
+                        beta
+                    } else {
+                        score
+                    };
+                }
+            }
+        }
+
+        let moves = board.get_legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        let hash_move = pv
+            .first()
+            .copied()
+            .or(entry.and_then(|entry| entry.best_move));
+        let previous = self.played.last().copied().flatten();
+        let mut picker = MovePicker::new(moves, hash_move, self.history, ply, previous);
+        let futile = prunable
+            && self.shared.options.futility
+            && depth <= FUTILITY_DEPTH
+            && eval + FUTILITY_MARGIN * depth <= alpha
+            && mate_in(alpha).is_none();
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        // Quiet moves searched without causing a cutoff.
+        let mut quiets = Vec::new();
+        let mut i = 0;
+        while let Some(mov) = picker.next(board, self.history) {
+            if ply == 0 && self.excluded.contains(&mov) {
+                continue;
+            }
+            // Only the first move follows the principal variation of the last iteration.
+            let child_pv = match pv.first() {
+                Some(&pv_move) if pv_move == mov => &pv[1..],
+                _ => &[],
+            };
+            let hash = board.hash();
+            let undo = board.make_move(mov);
+            let quiet = is_quiet(mov) && !board.in_check();
+            if futile && i > 0 && quiet {
+                board.undo_move(mov, undo);
+                continue;
+            }
+            self.hashes.push(hash);
+            self.played.push(Some(mov));
+            let mut score;
+            if i == 0 {
+                score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha, child_pv);
+            } else {
+                let mut reduction = 0;
+                if self.shared.options.late_move_reductions
+                    && depth >= LATE_MOVE_DEPTH
+                    && i >= LATE_MOVES
+                    && quiet
+                    && !in_check
+                {
+                    reduction =
+                        (late_move_reduction(depth, i) - pv_node as i32).clamp(0, depth - 2);
+                }
+                score = -self.negamax(
+                    board,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    child_pv,
+                );
+                if score > alpha && reduction > 0 && !self.stopped {
+                    score = -self.negamax(board, depth - 1, ply + 1, -alpha - 1, -alpha, child_pv);
+                }
+                if score > alpha && score < beta && !self.stopped {
+                    score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha, child_pv);
+                }
+            }
+            board.undo_move(mov, undo);
+            self.played.pop();
+            self.hashes.pop();
+            if self.stopped {
+                return 0;
+            }
+            i += 1;
+
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(mov);
+                    self.update_pv(ply, mov);
+                }
+                if alpha >= beta {
+                    if is_quiet(mov) {
+                        let color = board.color_to_move();
+                        self.history
+                            .update(color, ply, previous, mov, &quiets, depth);
+                    }
+                    break;
+                }
+            }
+            if is_quiet(mov) {
+                quiets.push(mov);
+            }
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // The root isn't fully searched when moves are left out.
+        if ply > 0 || self.excluded.is_empty() {
+            let entry = Entry {
+                best_move,
+                score: score_to_tt(best, ply),
+                depth: depth.min(u8::MAX as i32) as u8,
+                bound,
+            };
+            self.shared.tt.store(board.hash(), entry);
+        }
+        best
+    }
+
+    /// Searches captures and promotions until the position is quiet. In check, every
+    /// evasion is searched instead, so that mates are not missed.
+    fn quiescence(&mut self, board: &mut Board, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv[ply].clear();
+        self.visit();
+        if self.stopped {
+            return 0;
+        }
+        // Checks can go on past the deepest ply the search has room for.
+        if ply >= MAX_PLY {
+            return evaluate(board);
+        }
+
+        let in_check = board.in_check();
+        let moves = board.get_legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        let mut best = -INFINITY;
+        if !in_check {
+            // Standing pat: the side to move isn't forced to capture.
+            best = evaluate(board);
+            if best >= beta {
+                return best;
+            }
+            alpha = alpha.max(best);
+        }
+        let mut picker = match in_check {
+            true => {
+                let previous = self.played.last().copied().flatten();
+                MovePicker::new(moves, None, self.history, ply, previous)
+            }
+            false => MovePicker::captures(moves),
+        };
+
+        while let Some(mov) = picker.next(board, self.history) {
+            // Captures that lose material are very unlikely to raise alpha.
+            if !in_check && !board.see_ge(mov, 0) {
+                continue;
+            }
+            self.played.push(Some(mov));
+            let undo = board.make_move(mov);
+            let score = -self.quiescence(board, ply + 1, -beta, -alpha);
+            board.undo_move(mov, undo);
+            self.played.pop();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mov);
+                }
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    /// Counts a node and sets `stopped` if a limit has been reached.
+    fn visit(&mut self) {
+        self.nodes += 1;
+        if self
+            .limits
+            .nodes
+            .is_some_and(|nodes| self.total_nodes() > nodes)
+        {
+            self.stopped = true;
+        }
+        if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            self.flush_nodes();
+            if self.shared.stop.load(Ordering::Relaxed)
+                || self.shared.done.load(Ordering::Relaxed)
+                || self
+                    .limits
+                    .time
+                    .is_some_and(|time| self.shared.start.elapsed() >= time)
+            {
+                self.stopped = true;
+            }
+        }
+    }
+
+    /// Makes `mov` followed by the principal variation of the next ply the principal
+    /// variation of `ply`.
+    fn update_pv(&mut self, ply: usize, mov: Move) {
+        let (current, next) = self.pv.split_at_mut(ply + 1);
+        let pv = &mut current[ply];
+        pv.clear();
+        pv.push(mov);
+        pv.extend_from_slice(&next[0]);
+    }
+
+    /// Returns `true` if the position is drawn by the fifty-move rule, insufficient
+    /// material or a repetition.
+    fn is_draw(&self, board: &Board) -> bool {
+        if board.halfmove_clock() >= 100 || board.is_insufficient_material() {
+            return true;
+        }
+        // Positions before the last capture or pawn move can't repeat, and a single
+        // repetition is enough since the side to move could repeat it once more.
+        let reversible = (board.halfmove_clock() as usize).min(self.hashes.len());
+        self.hashes[self.hashes.len() - reversible..]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .any(|&hash| hash == board.hash())
+    }
+}
+
+#[test]
+fn finds_mates() {
+    use crate::parser::load_position_from_fen;
+
+    let mut searcher = Searcher::new();
+    // Back rank mate
+    let board = load_position_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+    let result = searcher.search(
+        &board,
+        &Limits {
+            depth: Some(3),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result.best_move.unwrap().to_string(), "a1a8");
+    assert_eq!(mate_in(result.score), Some(1));
+    // Mate in two with a queen sacrifice
+    let board = load_position_from_fen("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 1 1")
+        .unwrap();
+    let result = searcher.search(
+        &board,
+        &Limits {
+            depth: Some(4),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result.pv[0].to_string(), "d5d8");
+    assert_eq!(mate_in(result.score), Some(2));
+    assert_eq!(result.pv.len(), 3);
+    // The side to move is mated or stalemated
+    let board = load_position_from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+    let result = searcher.search(&board, &Limits::default());
+    assert_eq!((result.best_move, result.score), (None, -MATE));
+    let board = load_position_from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    let result = searcher.search(&board, &Limits::default());
+    assert_eq!((result.best_move, result.score), (None, 0));
+
+    // Mate and tablebase scores are stored relative to the node, other scores as they are
+    for (score, stored) in [
+        (MATE - 7, MATE - 2),
+        (-MATE + 7, -MATE + 2),
+        (TB_WIN - 9, TB_WIN - 4),
+        (-TB_WIN + 9, -TB_WIN + 4),
+        (900, 900),
+    ] {
+        assert_eq!(score_to_tt(score, 5), stored);
+        assert_eq!(score_from_tt(stored, 5), score);
+    }
+}
+
+#[test]
+fn wins_material_and_avoids_draws() {
+    use crate::parser::load_position_from_fen;
+
+    let mut searcher = Searcher::new();
+    // The knight forks king and queen
+    let board = load_position_from_fen("4k3/7p/8/1q6/2N5/8/7P/4K3 w - - 0 1").unwrap();
+    let result = searcher.search(
+        &board,
+        &Limits {
+            depth: Some(4),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result.best_move.unwrap().to_string(), "c4d6");
+    assert!(result.score > 200);
+    // Down a queen against a knight, black repeats the position to draw
+    let mut game = Game::new(load_position_from_fen("kn6/8/8/8/8/7Q/8/K7 b - - 0 1").unwrap());
+    for text in ["b8c6", "h3h4", "c6b8", "h4h3"] {
+        game.play(game.board().move_from_notation(text).unwrap())
+            .unwrap();
+    }
+    let result = searcher.search_game(
+        &game,
+        &Limits {
+            depth: Some(3),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result.best_move.unwrap().to_string(), "b8c6");
+    assert_eq!(result.score, 0);
+    let result = searcher.search(
+        game.board(),
+        &Limits {
+            depth: Some(3),
+            ..Default::default()
+        },
+    );
+    assert!(result.score < -500);
+    // Insufficient material
+    let board = load_position_from_fen("7k/8/8/8/8/8/8/K6N w - - 0 1").unwrap();
+    let result = searcher.search(
+        &board,
+        &Limits {
+            depth: Some(3),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result.score, 0);
+}
+
+#[test]
+fn respects_limits() {
+    use crate::parser::load_position_from_fen;
+
+    let mut searcher = Searcher::new();
+    let board = Board::new();
+    let result = searcher.search(
+        &board,
+        &Limits {
+            depth: Some(3),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result.depth, 3);
+    assert_eq!(result.pv.len(), 3);
+
+    let result = searcher.search(
+        &board,
+        &Limits {
+            nodes: Some(5000),
+            ..Default::default()
+        },
+    );
+    assert!(result.nodes <= 5001);
+    assert!(result.best_move.is_some());
+
+    // The time is only checked every `TIME_CHECK_INTERVAL` nodes, so a search out of time
+    // goes at most that far before stopping
+    let result = searcher.search(
+        &board,
+        &Limits {
+            time: Some(Duration::ZERO),
+            ..Default::default()
+        },
+    );
+    assert!(result.nodes <= TIME_CHECK_INTERVAL);
+    assert!(result.best_move.is_some());
+    // Every iteration is reported, and the stop flag ends the search right away
+    let mut depths = Vec::new();
+    let game = Game::new(board);
+    let limits = Limits {
+        depth: Some(4),
+        ..Default::default()
+    };
+    searcher.search_game_with(&game, &limits, |result| depths.push(result.depth));
+    assert_eq!(depths, [1, 2, 3, 4]);
+    searcher.stop_flag().store(true, Ordering::Relaxed);
+    let result = searcher.search_game(&game, &Limits::default());
+    assert!(result.best_move.is_some());
+    assert!(result.nodes <= TIME_CHECK_INTERVAL);
+    searcher.stop_flag().store(false, Ordering::Relaxed);
+
+    // The only legal reply is played right away, whatever the time left
+    let board = load_position_from_fen("k7/8/8/8/8/8/1r6/K1r5 w - - 0 1").unwrap();
+    let limits = Limits {
+        clock: Some(Clock {
+            wtime: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let result = searcher.search(&board, &limits);
+    assert_eq!(result.best_move.unwrap().to_string(), "a1b2");
+    assert_eq!(result.depth, 1);
+
+    // The quiescence search stops at the deepest ply, even in check
+    let mut board = load_position_from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+    let (done, total_nodes) = (AtomicBool::new(false), AtomicU64::new(0));
+    let shared = Shared {
+        tt: &searcher.tt,
+        options: searcher.options,
+        start: Instant::now(),
+        stop: &searcher.stop,
+        done: &done,
+        total_nodes: &total_nodes,
+        tablebases: None,
+    };
+    let mut history = History::new();
+    let game = Game::new(board.clone());
+    let mut worker = Worker::new(shared, Limits::default(), &game, &mut history);
+    let score = worker.quiescence(&mut board, MAX_PLY, -INFINITY, INFINITY);
+    assert_eq!(score, evaluate(&board));
+}
+
+#[test]
+fn search_options() {
+    use crate::parser::load_position_from_fen;
+
+    let fork = load_position_from_fen("4k3/7p/8/1q6/2N5/8/7P/4K3 w - - 0 1").unwrap();
+    let mate = load_position_from_fen("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 1 1")
+        .unwrap();
+    let limits = Limits {
+        depth: Some(6),
+        ..Default::default()
+    };
+    let mut searcher = Searcher::new();
+    for (i, options) in [SearchOptions::ALL, SearchOptions::NONE]
+        .into_iter()
+        .enumerate()
+    {
+        // With all the techniques, each one switched off alone then all of them. With none
+        // of them, each one switched on alone then none of them.
+        for off in 0..8 {
+            let mut options = options;
+            let flags = [
+                &mut options.null_move,
+                &mut options.late_move_reductions,
+                &mut options.reverse_futility,
+                &mut options.futility,
+                &mut options.razoring,
+                &mut options.check_extensions,
+                &mut options.aspiration_windows,
+            ];
+            if let Some(flag) = flags.into_iter().nth(off) {
+                *flag = i == 1;
+            }
+            *searcher.options_mut() = options;
+            searcher.clear();
+            let result = searcher.search(&fork, &limits);
+            assert_eq!(result.best_move.unwrap().to_string(), "c4d6");
+            let result = searcher.search(&mate, &limits);
+            assert_eq!(mate_in(result.score), Some(2));
+        }
+    }
+    // Pruning and reductions visit fewer nodes
+    let mut nodes = Vec::new();
+    for options in [SearchOptions::ALL, SearchOptions::NONE] {
+        *searcher.options_mut() = options;
+        searcher.clear();
+        nodes.push(searcher.search(&Board::new(), &limits).nodes);
+    }
+    assert!(nodes[0] * 2 < nodes[1]);
+}
+
+#[test]
+fn multiple_threads() {
+    use crate::parser::load_position_from_fen;
+
+    // A single thread always searches the same tree
+    let mut searcher = Searcher::new();
+    let limits = Limits {
+        depth: Some(5),
+        ..Default::default()
+    };
+    let results: Vec<_> = (0..2)
+        .map(|_| {
+            searcher.clear();
+            let result = searcher.search(&Board::new(), &limits);
+            (result.pv, result.score, result.nodes)
+        })
+        .collect();
+    assert_eq!(results[0], results[1]);
+
+    searcher.set_threads(4);
+    assert_eq!(searcher.threads(), 4);
+    let board = load_position_from_fen("4k3/7p/8/1q6/2N5/8/7P/4K3 w - - 0 1").unwrap();
+    let result = searcher.search(&board, &limits);
+    assert_eq!(result.best_move.unwrap().to_string(), "c4d6");
+    // Helpers count towards the node limit, and stop along with the main thread
+    let result = searcher.search(
+        &Board::new(),
+        &Limits {
+            nodes: Some(20_000),
+            ..Default::default()
+        },
+    );
+    assert!(result.nodes <= 20_000 + 4 * TIME_CHECK_INTERVAL);
+    searcher.stop_flag().store(true, Ordering::Relaxed);
+    let result = searcher.search(&Board::new(), &Limits::default());
+    assert!(result.best_move.is_some());
+    assert!(result.nodes <= 4 * TIME_CHECK_INTERVAL);
+    searcher.set_threads(0);
+    assert_eq!(searcher.threads(), 1);
+}
+
+#[test]
+fn multiple_lines() {
+    use crate::parser::load_position_from_fen;
+
+    let mut searcher = Searcher::new();
+    let limits = Limits {
+        depth: Some(5),
+        ..Default::default()
+    };
+    let board = load_position_from_fen("4k3/7p/8/1q6/2N5/8/7P/4K3 w - - 0 1").unwrap();
+    let result = searcher.search(&board, &limits);
+    assert_eq!(result.lines.len(), 1);
+    assert_eq!(
+        (result.lines[0].score, &result.lines[0].moves),
+        (result.score, &result.pv)
+    );
+
+    searcher.set_multi_pv(3);
+    let result = searcher.search(&board, &limits);
+    assert_eq!(result.lines.len(), 3);
+    assert_eq!(result.best_move.unwrap().to_string(), "c4d6");
+    assert_eq!(
+        (result.lines[0].score, &result.lines[0].moves),
+        (result.score, &result.pv)
+    );
+    // Lines start with distinct moves, from the best to the worst
+    for (i, line) in result.lines.iter().enumerate() {
+        for other in &result.lines[i + 1..] {
+            assert!(line.score >= other.score);
+            assert_ne!(line.moves[0], other.moves[0]);
+        }
+    }
+    assert!(result.lines[1].score < result.score - 200);
+    // There can't be more lines than legal moves
+    let board = load_position_from_fen("k7/8/8/8/8/8/1r6/K1r5 w - - 0 1").unwrap();
+    assert_eq!(searcher.search(&board, &limits).lines.len(), 1);
+}
@@ -0,0 +1,177 @@
+//! Static exchange evaluation (SEE).
+//!
+//! SEE tells what a move wins or loses in material once every piece attacking its target
+//! square has captured on it, in order from the least valuable one, each side being free
+//! to stop capturing when it would lose material. Pieces behind the ones that capture, on
+//! the same line, join the exchange when their way is cleared (x-rays).
+//!
+//! Pins and checks are ignored, apart from the king that only captures when the square
+//! isn't defended anymore.
+
+use crate::attacks::{bishop_attacks, rook_attacks};
+use crate::bitboard::{Board, Move, MoveKind, Square};
+use crate::piece::{Color, Piece, PieceType};
+
+/// Returns the value of a piece of type `piece_type` in exchanges.
+pub fn see_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 320,
+        PieceType::Bishop => 330,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 20_000,
+    }
+}
+
+impl Board {
+    /// Returns the material won by the side to move when playing `mov` and trading pieces on
+    /// its target square for as long as it pays off, see the `see` module.
+    ///
+    /// Quiet moves score 0 unless they put the piece where it can be taken for less than
+    /// its value.
+    pub fn see(&self, mov: Move) -> i32 {
+        if mov.is_castling() {
+            return 0;
+        }
+        let (origin, target) = (mov.origin(), mov.target());
+        let Some(piece) = self.at(origin.index as usize) else {
+            return 0;
+        };
+        let mut occupancy = self.occupancy() & !origin.bitmask();
+        let captured = match mov.kind() {
+            MoveKind::EnPassant => {
+                // The captured pawn stands next to the origin square, on the target file.
+                let square = Square {
+                    index: origin.get_rank() * 8 + target.get_file(),
+                };
+                occupancy &= !square.bitmask();
+                Some(PieceType::Pawn)
+            }
+            _ => self
+                .at(target.index as usize)
+                .map(|piece| piece.piece_type()),
+        };
+
+        // gains[i] is the material won by the side that makes the i-th capture, assuming
+        // the exchange ends right after it.
+        let mut gains = Vec::with_capacity(32);
+        let mut on_square = piece.piece_type();
+        let mut gain = captured.map_or(0, see_value);
+        if let Some(promotion) = mov.promotion() {
+            gain += see_value(promotion) - see_value(PieceType::Pawn);
+            on_square = promotion;
+        }
+        gains.push(gain);
+
+        let piece_types = |piece_type| {
+            self.pieces(Piece::new(piece_type, Color::White))
+                | self.pieces(Piece::new(piece_type, Color::Black))
+        };
+        let queens = piece_types(PieceType::Queen);
+        let diagonal = piece_types(PieceType::Bishop) | queens;
+        let straight = piece_types(PieceType::Rook) | queens;
+        let mut attackers = self.attackers_to(target, occupancy) & occupancy;
+        let mut color = !piece.color();
+        loop {
+            let own = attackers & self.color_occupancy(color);
+            let Some((piece_type, attacker)) = least_valuable(self, own, color) else {
+                break;
+            };
+            // The king can't capture a defended piece.
+            if piece_type == PieceType::King && attackers & !own != 0 {
+                break;
+            }
+            gains.push(see_value(on_square) - gains[gains.len() - 1]);
+            on_square = piece_type;
+
+            // Moving the attacker out of the way may uncover a slider behind it.
+            occupancy &= !attacker;
+            if matches!(
+                piece_type,
+                PieceType::Pawn | PieceType::Bishop | PieceType::Queen
+            ) {
+                attackers |= bishop_attacks(target.index, occupancy) & diagonal;
+            }
+            if matches!(piece_type, PieceType::Rook | PieceType::Queen) {
+                attackers |= rook_attacks(target.index, occupancy) & straight;
+            }
+            attackers &= occupancy;
+            color = !color;
+        }
+
+        // Each side only makes a capture if it gains more than stopping before it.
+        for i in (1..gains.len()).rev() {
+            gains[i - 1] = -(-gains[i - 1]).max(gains[i]);
+        }
+        gains[0]
+    }
+
+    /// Returns `true` if `see` of `mov` is at least `threshold`.
+    pub fn see_ge(&self, mov: Move, threshold: i32) -> bool {
+        self.see(mov) >= threshold
+    }
+}
+
+/// Returns the type and the square, as a bitmask, of the least valuable piece of `color`
+/// among `attackers`.
+fn least_valuable(board: &Board, attackers: u64, color: Color) -> Option<(PieceType, u64)> {
+    [
+        PieceType::Pawn,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+    ]
+    .into_iter()
+    .find_map(|piece_type| {
+        let pieces = attackers & board.pieces(Piece::new(piece_type, color));
+        // Any of them will do, the most significant bit is the cheapest to isolate.
+        (pieces != 0).then(|| (piece_type, (1 << 63) >> pieces.leading_zeros()))
+    })
+}
+
+#[test]
+fn static_exchanges() {
+    use crate::parser::load_position_from_fen;
+
+    for (fen, mov, see) in [
+        // Undefended pawn
+        (
+            "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+            "e1e5",
+            100,
+        ),
+        // Pawn defended by a pawn, backed up by the rook behind it
+        (
+            "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+            "d3e5",
+            -220,
+        ),
+        // Rook x-rayed by the queen behind it, each side recapturing in turn
+        ("4k3/8/8/3r4/8/8/3R4/3QK3 w - - 0 1", "d2d5", 500),
+        ("3rk3/8/8/3r4/8/8/3R4/3QK3 w - - 0 1", "d2d5", 500),
+        ("3qk3/3r4/8/3r4/8/8/3R4/3QK3 w - - 0 1", "d2d5", 0),
+        // The queen takes a defended pawn
+        ("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", "d1d5", -800),
+        // Quiet moves onto an attacked square
+        ("4k3/8/2p5/8/8/8/8/3QK3 w - - 0 1", "d1d5", -900),
+        ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", "d1d5", 0),
+        // En passant, and a promotion the rook recaptures
+        ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 100),
+        ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q", 800 - 900),
+        ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8q", 500 + 800),
+        // The king can't take a defended piece
+        ("4k3/8/8/8/8/8/3r4/3RK3 w - - 0 1", "d1d2", 500),
+        ("4k3/8/8/8/8/8/3r4/3RK3 b - - 0 1", "d2d1", 0),
+        ("4k3/8/8/8/8/8/3r4/3RKN2 b - - 0 1", "d2e2", -500),
+        ("4k3/8/8/7b/8/8/3r4/3RK3 b - - 0 1", "d2e2", 0),
+    ] {
+        let board = load_position_from_fen(fen).unwrap();
+        let mov = board.move_from_notation(mov).unwrap();
+        assert_eq!(board.see(mov), see, "{} {}", fen, mov);
+        assert!(board.see_ge(mov, see));
+        assert!(!board.see_ge(mov, see + 1));
+    }
+}
@@ -0,0 +1,1692 @@
+//! Syzygy endgame tablebases.
+//!
+//! Syzygy tablebases tell, for every position with few enough pieces, how the game ends
+//! under perfect play (WDL tables, in `.rtbw` files) and how far the winning side is from
+//! the next capture or pawn move that keeps the win (DTZ tables, in `.rtbz` files). Wins
+//! and losses that the fifty-move rule turns into draws are told apart from the others.
+//!
+//! Tables leave out positions with castling rights, and the values of positions where a
+//! capture is best may be anything: probes play the captures first and keep the best of
+//! their results and of the stored value. DTZ tables only store one side to move, the
+//! other one is found by playing every move.
+//!
+//! The files are Huffman-compressed indices of positions, read whole the first time a
+//! position of their material is probed. The format and the probing code follow the
+//! generator of the tables, <https://github.com/syzygy1/tb>, and the probing code of
+//! Stockfish.
+
+use crate::bitboard::{Board, CastleRights, Move};
+use crate::bits;
+use crate::errors::{Error, Result};
+use crate::piece::{Color, Piece, PieceType};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Neg;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Most pieces, kings included, a table holds.
+pub const MAX_PIECES: usize = 7;
+/// Rank of the root moves that win before the fifty-move rule draws.
+const MAX_DTZ: i32 = 1 << 18;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// Flags of a file header.
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+// Flags of a table.
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+/// The outcome of a position under perfect play, for the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Wdl {
+    Loss = -2,
+    /// A loss the fifty-move rule turns into a draw.
+    BlessedLoss = -1,
+    Draw = 0,
+    /// A win the fifty-move rule turns into a draw.
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Self {
+        match value {
+            ..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Self;
+
+    /// Returns the outcome for the other side.
+    fn neg(self) -> Self {
+        Self::from_value(-(self as i32))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+/// What probing a table found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TableProbe {
+    Value(i32),
+    /// The table only stores the other side to move.
+    ChangeSideToMove,
+}
+
+/// Tables turning sets of squares into indices in the files.
+struct Indices {
+    /// Encodes the squares below the a1-h8 diagonal to 0..28.
+    map_b1h1h7: [usize; 64],
+    /// Encodes the squares of the a1-d1-d4 triangle to 0..10, those on the diagonal last.
+    map_a1d1d4: [usize; 64],
+    /// Encodes the 462 positions of two kings where the first is in the a1-d1-d4 triangle
+    /// and, if it's on the diagonal, the second is not above the diagonal.
+    map_kk: [[usize; 64]; 10],
+    /// Encodes the squares a2-h7 to 0..48, in the order pawns lead.
+    map_pawns: [usize; 64],
+    /// Number of ways to choose `k` squares among `n`, indexed by `k` then `n`.
+    binomial: [[u64; 64]; 6],
+    /// First index of the positions of `count` pawns led by the pawn on `square`.
+    lead_pawn_index: [[u64; 64]; 6],
+    /// Number of positions of `count` pawns led by a pawn on `file`.
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+impl Indices {
+    fn new() -> Self {
+        let mut map_b1h1h7 = [0; 64];
+        for (code, square) in (0..64).filter(|&s| off_diagonal(s) < 0).enumerate() {
+            map_b1h1h7[square] = code;
+        }
+
+        let mut map_a1d1d4 = [0; 64];
+        let triangle = (0..=27).filter(|&square| square % 8 <= 3);
+        let (below, on): (Vec<_>, Vec<_>) = triangle
+            .filter(|&square| off_diagonal(square) <= 0)
+            .partition(|&square| off_diagonal(square) < 0);
+        for (code, square) in below.into_iter().chain(on).enumerate() {
+            map_a1d1d4[square] = code;
+        }
+
+        let mut map_kk = [[0; 64]; 10];
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for (index, codes) in map_kk.iter_mut().enumerate() {
+            // b1 is the only square of the triangle mapped to 0.
+            let first = (0..=27)
+                .find(|&square| map_a1d1d4[square] == index && (index > 0 || square == 1))
+                .expect("Every code of the triangle is used");
+            for (second, second_code) in codes.iter_mut().enumerate() {
+                let distance = (first % 8)
+                    .abs_diff(second % 8)
+                    .max((first / 8).abs_diff(second / 8));
+                if distance <= 1 || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((index, second));
+                } else {
+                    *second_code = code;
+                    code += 1;
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            map_kk[index][second] = code;
+            code += 1;
+        }
+
+        let mut binomial = [[0; 64]; 6];
+        binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                binomial[k][n] = if k > 0 { binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // A leading pawn on a square leaves the squares before it, and their mirror on the
+        // other half of the board, to the other pawns: 47 squares when it's on a2, 45 on a3.
+        let mut map_pawns = [0; 64];
+        for file in 0..4 {
+            for rank in 1..7 {
+                let code = 47 - 2 * (file * 6 + rank - 1);
+                map_pawns[rank * 8 + file] = code;
+                map_pawns[rank * 8 + (7 - file)] = code - 1;
+            }
+        }
+        let mut lead_pawn_index = [[0; 64]; 6];
+        let mut lead_pawns_size = [[0; 4]; 6];
+        for count in 1..6 {
+            for (file, size) in lead_pawns_size[count].iter_mut().enumerate() {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    lead_pawn_index[count][square] = index;
+                    index += binomial[count - 1][map_pawns[square]];
+                }
+                *size = index;
+            }
+        }
+
+        Self {
+            map_b1h1h7,
+            map_a1d1d4,
+            map_kk,
+            map_pawns,
+            binomial,
+            lead_pawn_index,
+            lead_pawns_size,
+        }
+    }
+}
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(Indices::new)
+}
+
+/// Returns the rank minus the file of `square`: negative below the a1-h8 diagonal, 0 on it
+/// and positive above.
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+/// Returns the mirror of `square` across the a1-h8 diagonal.
+fn flip_diagonal(square: usize) -> usize {
+    (square % 8) * 8 + square / 8
+}
+
+/// Returns the code of `piece` in table files: 1 to 6 from pawn to king, plus 8 for black.
+fn piece_code(piece: Piece) -> u8 {
+    piece.piece_type() as u8 + 1 + if piece.color() == Color::Black { 8 } else { 0 }
+}
+
+/// Returns the pieces of `color` on `board` as table files name them, such as "KRP".
+fn material_name(board: &Board, color: Color) -> String {
+    let mut name = String::new();
+    for (piece_type, letter) in [
+        (PieceType::King, 'K'),
+        (PieceType::Queen, 'Q'),
+        (PieceType::Rook, 'R'),
+        (PieceType::Bishop, 'B'),
+        (PieceType::Knight, 'N'),
+        (PieceType::Pawn, 'P'),
+    ] {
+        let count = bits::count_ones(board.pieces(Piece::new(piece_type, color)));
+        name.extend(std::iter::repeat_n(letter, count as usize));
+    }
+    name
+}
+
+/// Returns `true` if the piece `mov` moves on `board` is a pawn.
+fn is_pawn_move(board: &Board, mov: Move) -> bool {
+    board
+        .at(mov.origin().index as usize)
+        .is_some_and(|piece| piece.piece_type() == PieceType::Pawn)
+}
+
+/// Returns `true` if the side to move is checkmated.
+fn is_mate(board: &Board) -> bool {
+    board.in_check() && board.get_legal_moves().is_empty()
+}
+
+/// Returns the distance to zeroing of a position where the best move captures or moves a
+/// pawn and leads to `wdl`.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+/// The material of the positions of a file, as its name gives it: the pieces of the side
+/// named first, played by white in the tables, then those of the other side.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Material {
+    piece_count: usize,
+    has_pawns: bool,
+    /// Some side has a single piece of a type other than king.
+    has_unique_pieces: bool,
+    /// Pawns of the color whose pawns lead, the one with fewer pawns if both have some,
+    /// then of the other color.
+    pawn_counts: [usize; 2],
+    /// Both sides have the same pieces.
+    symmetric: bool,
+}
+
+impl Material {
+    /// Parses a name such as "KRPvKR", returns `None` if it isn't the name of a table.
+    fn from_name(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let counts = |side: &str| {
+            let mut counts = [0; 6];
+            for (i, letter) in side.chars().enumerate() {
+                let piece_type = match letter {
+                    'P' => PieceType::Pawn,
+                    'N' => PieceType::Knight,
+                    'B' => PieceType::Bishop,
+                    'R' => PieceType::Rook,
+                    'Q' => PieceType::Queen,
+                    'K' => PieceType::King,
+                    _ => return None,
+                };
+                // The king comes first, and only once.
+                if (piece_type == PieceType::King) != (i == 0) {
+                    return None;
+                }
+                counts[piece_type as usize] += 1;
+            }
+            (counts[PieceType::King as usize] == 1).then_some(counts)
+        };
+        let (white_counts, black_counts) = (counts(white)?, counts(black)?);
+        let piece_count = white.len() + black.len();
+        if piece_count > MAX_PIECES {
+            return None;
+        }
+        let (white_pawns, black_pawns) = (white_counts[0], black_counts[0]);
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        Some(Self {
+            piece_count,
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: [white_counts, black_counts]
+                .iter()
+                .any(|counts| counts[..PieceType::King as usize].contains(&1)),
+            pawn_counts: match white_leads {
+                true => [white_pawns, black_pawns],
+                false => [black_pawns, white_pawns],
+            },
+            symmetric: white == black,
+        })
+    }
+}
+
+/// Returns `N` bytes of `bytes` from `offset`, or `None` past the end.
+fn read<const N: usize>(bytes: &[u8], offset: usize) -> Option<[u8; N]> {
+    let end = offset.checked_add(N)?;
+    bytes.get(offset..end)?.try_into().ok()
+}
+
+fn read_u8(bytes: &[u8], offset: usize) -> Result<u8> {
+    Ok(read::<1>(bytes, offset).ok_or(Error::InvalidTablebase)?[0])
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(
+        read(bytes, offset).ok_or(Error::InvalidTablebase)?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(
+        read(bytes, offset).ok_or(Error::InvalidTablebase)?,
+    ))
+}
+
+/// How to decode one table of a file, the values of one side to move with the leading
+/// pawn on one file. Offsets are from the start of the file.
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    flags: u8,
+    /// Codes of the pieces, in the order they're indexed.
+    pieces: [u8; MAX_PIECES],
+    /// Lengths of the groups of pieces indexed together, ended by 0.
+    group_length: [usize; MAX_PIECES + 1],
+    /// Factor of the index of each group, the last one being the number of positions.
+    group_index: [u64; MAX_PIECES + 1],
+    block_size: usize,
+    /// Number of positions between two entries of the sparse index.
+    span: u64,
+    blocks: usize,
+    min_symbol_length: usize,
+    /// The value of every position, for tables with a single value.
+    single_value: u16,
+    lowest_symbols: usize,
+    /// Lowest symbol of each length, left-aligned on 64 bits.
+    base64: Vec<u64>,
+    /// Number of values each symbol stands for, minus one.
+    symbol_lengths: Vec<u8>,
+    btree: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_lengths: usize,
+    block_lengths_size: usize,
+    data: usize,
+    /// Offsets in the DTZ map of the values of each outcome.
+    map_index: [usize; 4],
+}
+
+impl PairsData {
+    /// Splits the pieces into groups and computes the factors of their indices, with the
+    /// groups indexed in the order `order` gives for the leading and the pawns groups.
+    fn set_groups(&mut self, material: &Material, order: [u8; 2], file: usize) -> Result<()> {
+        let indices = indices();
+        let mut n = 0;
+        let mut first_length = match (material.has_pawns, material.has_unique_pieces) {
+            (true, _) => 0,
+            (false, true) => 3,
+            (false, false) => 2,
+        };
+        self.group_length[0] = 1;
+        for i in 1..material.piece_count {
+            first_length -= 1;
+            if first_length > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_length[n] += 1;
+            } else {
+                n += 1;
+                self.group_length[n] = 1;
+            }
+        }
+        n += 1;
+        self.group_length[n] = 0;
+        if self.group_length[..n].iter().any(|&length| length > 5) {
+            return Err(Error::InvalidTablebase);
+        }
+
+        // Pawns of both sides make the first two groups.
+        let both_pawns = material.has_pawns && material.pawn_counts[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares =
+            64 - self.group_length[0] - if both_pawns { self.group_length[1] } else { 0 };
+        let mut index = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                self.group_index[0] = index;
+                index *= match (material.has_pawns, material.has_unique_pieces) {
+                    (true, _) => indices.lead_pawns_size[self.group_length[0]][file],
+                    (false, true) => 31_332,
+                    (false, false) => 462,
+                };
+            } else if k == order[1] {
+                self.group_index[1] = index;
+                index *= indices.binomial[self.group_length[1]][48 - self.group_length[0]];
+            } else {
+                self.group_index[next] = index;
+                let length = self.group_length[next];
+                index *= indices.binomial[length][free_squares];
+                free_squares = free_squares
+                    .checked_sub(length)
+                    .ok_or(Error::InvalidTablebase)?;
+                next += 1;
+            }
+            k += 1;
+            if k > 15 {
+                return Err(Error::InvalidTablebase);
+            }
+        }
+        self.group_index[n] = index;
+        Ok(())
+    }
+
+    /// Reads the sizes and the Huffman codes of the table from `offset`, returns the
+    /// offset right after them.
+    fn read_sizes(&mut self, bytes: &[u8], mut offset: usize) -> Result<usize> {
+        self.flags = read_u8(bytes, offset)?;
+        if self.flags & SINGLE_VALUE != 0 {
+            self.single_value = read_u8(bytes, offset + 1)? as u16;
+            return Ok(offset + 2);
+        }
+        let length = self.group_length.iter().position(|&length| length == 0);
+        let size = self.group_index[length.unwrap_or(MAX_PIECES)];
+        let shift = |offset, max| match read_u8(bytes, offset)? {
+            shift if shift <= max => Ok(shift),
+            _ => Err(Error::InvalidTablebase),
+        };
+        self.block_size = 1 << shift(offset + 1, 31)?;
+        self.span = 1 << shift(offset + 2, 63)?;
+        self.sparse_index_size = size.div_ceil(self.span) as usize;
+        let padding = read_u8(bytes, offset + 3)? as usize;
+        self.blocks = read_u32(bytes, offset + 4)? as usize;
+        // The padding keeps the sparse index from pointing past the block lengths.
+        self.block_lengths_size = self.blocks + padding;
+        let max_symbol_length = read_u8(bytes, offset + 8)? as usize;
+        self.min_symbol_length = read_u8(bytes, offset + 9)? as usize;
+        if self.min_symbol_length == 0 || max_symbol_length < self.min_symbol_length {
+            return Err(Error::InvalidTablebase);
+        }
+        offset += 10;
+
+        self.lowest_symbols = offset;
+        let lengths = max_symbol_length - self.min_symbol_length + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = read_u16(bytes, offset + 2 * i)? as u64;
+            let next_lowest = read_u16(bytes, offset + 2 * i + 2)? as u64;
+            self.base64[i] = self.base64[i + 1]
+                .wrapping_add(lowest)
+                .wrapping_sub(next_lowest)
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base
+                .checked_shl((64 - i - self.min_symbol_length) as u32)
+                .unwrap_or(0);
+        }
+        offset += 2 * lengths;
+
+        let symbols = read_u16(bytes, offset)? as usize;
+        offset += 2;
+        self.btree = offset;
+        if offset + 3 * symbols > bytes.len() {
+            return Err(Error::InvalidTablebase);
+        }
+        self.symbol_lengths = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symbol_lengths[symbol] = self.symbol_length(bytes, symbol, &mut visited)?;
+            }
+        }
+        Ok(offset + 3 * symbols + (symbols & 1))
+    }
+
+    /// Computes the number of values `symbol` stands for, minus one, from its pair.
+    fn symbol_length(&mut self, bytes: &[u8], symbol: usize, visited: &mut [bool]) -> Result<u8> {
+        visited[symbol] = true;
+        let (left, right) = self.pair(bytes, symbol).ok_or(Error::InvalidTablebase)?;
+        if right == 0xfff {
+            return Ok(0);
+        }
+        if left >= visited.len() || right >= visited.len() {
+            return Err(Error::InvalidTablebase);
+        }
+        for child in [left, right] {
+            if !visited[child] {
+                self.symbol_lengths[child] = self.symbol_length(bytes, child, visited)?;
+            }
+        }
+        Ok(self.symbol_lengths[left]
+            .wrapping_add(self.symbol_lengths[right])
+            .wrapping_add(1))
+    }
+
+    /// Returns the two symbols `symbol` expands to, 12 bits each. Leaves store their value
+    /// as the left symbol and 0xfff as the right one.
+    fn pair(&self, bytes: &[u8], symbol: usize) -> Option<(usize, usize)> {
+        let [first, second, third] = read(bytes, self.btree + 3 * symbol)?;
+        let left = ((second as usize & 0xf) << 8) | first as usize;
+        let right = ((third as usize) << 4) | (second as usize >> 4);
+        Some((left, right))
+    }
+
+    fn block_length(&self, bytes: &[u8], block: usize) -> Option<i64> {
+        if block >= self.block_lengths_size {
+            return None;
+        }
+        let length = read(bytes, self.block_lengths + 2 * block)?;
+        Some(u16::from_le_bytes(length) as i64)
+    }
+
+    /// Returns the value of the position at `index`.
+    fn decompress(&self, bytes: &[u8], index: u64) -> Option<u16> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Some(self.single_value);
+        }
+        // The sparse index gives the block and the offset in it of every `span`-th
+        // position, from which the blocks are walked to the position.
+        let k = (index / self.span) as usize;
+        if k >= self.sparse_index_size {
+            return None;
+        }
+        let entry: [u8; 6] = read(bytes, self.sparse_index + 6 * k)?;
+        let mut block = u32::from_le_bytes(entry[..4].try_into().ok()?) as usize;
+        let mut offset = u16::from_le_bytes([entry[4], entry[5]]) as i64;
+        offset += (index % self.span) as i64 - (self.span / 2) as i64;
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += self.block_length(bytes, block)? + 1;
+        }
+        while offset > self.block_length(bytes, block)? {
+            offset -= self.block_length(bytes, block)? + 1;
+            block += 1;
+        }
+
+        // The last block may end before the bits read ahead of the symbols.
+        let mut position = self.data + block * self.block_size;
+        let mut next_bytes = |count: usize| {
+            let value = (0..count).fold(0, |value, i| {
+                (value << 8) | *bytes.get(position + i).unwrap_or(&0) as u64
+            });
+            position += count;
+            value
+        };
+        let mut buffer = next_bytes(8);
+        let mut buffer_size = 64;
+        let mut symbol;
+        loop {
+            // Symbols of a length are consecutive and left-aligned on 64 bits, they're
+            // between the lowest symbols of their length and of the one before.
+            let mut length = 0;
+            while buffer < *self.base64.get(length)? {
+                length += 1;
+            }
+            let shift = 64usize.checked_sub(length + self.min_symbol_length)?;
+            symbol = ((buffer - self.base64[length]) >> shift) as u16;
+            let lowest = read(bytes, self.lowest_symbols + 2 * length)?;
+            symbol = symbol.wrapping_add(u16::from_le_bytes(lowest));
+            let symbol_length = *self.symbol_lengths.get(symbol as usize)? as i64;
+            if offset < symbol_length + 1 {
+                break;
+            }
+            offset -= symbol_length + 1;
+            length += self.min_symbol_length;
+            buffer = buffer.checked_shl(length as u32).unwrap_or(0);
+            buffer_size -= length as i32;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= next_bytes(4)
+                    .checked_shl((64 - buffer_size) as u32)
+                    .unwrap_or(0);
+            }
+        }
+
+        // The symbol stands for several values, found by expanding its pairs.
+        let mut symbol = symbol as usize;
+        let mut symbol_length = self.symbol_lengths[symbol];
+        while symbol_length != 0 {
+            let (left, right) = self.pair(bytes, symbol)?;
+            let left_length = *self.symbol_lengths.get(left)?;
+            if offset < left_length as i64 + 1 {
+                symbol = left;
+            } else {
+                offset -= left_length as i64 + 1;
+                symbol = right;
+            }
+            // Children stand for fewer values than their parent, unless the file is corrupted.
+            let child_length = *self.symbol_lengths.get(symbol)?;
+            if child_length >= symbol_length {
+                return None;
+            }
+            symbol_length = child_length;
+        }
+        Some(self.pair(bytes, symbol)?.0 as u16)
+    }
+}
+
+/// A table file loaded in memory.
+struct Table {
+    bytes: Vec<u8>,
+    /// Decoding data, indexed by file of the leading pawn then by side to move.
+    items: [[PairsData; 2]; 4],
+    /// Number of sides to move stored.
+    sides: usize,
+    /// Offset of the map of DTZ values.
+    dtz_map: usize,
+}
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Table")
+            .field("size", &self.bytes.len())
+            .field("sides", &self.sides)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Table {
+    /// Reads the headers of `bytes`, the contents of a file of `kind` for `material`.
+    fn parse(bytes: Vec<u8>, material: &Material, kind: TableKind) -> Result<Self> {
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if !bytes.starts_with(&magic) {
+            return Err(Error::InvalidTablebase);
+        }
+        let header = read_u8(&bytes, 4)?;
+        if (header & HAS_PAWNS != 0) != material.has_pawns
+            || (header & SPLIT != 0) == material.symmetric
+        {
+            return Err(Error::InvalidTablebase);
+        }
+
+        // WDL files store both sides to move, DTZ files the one that compresses better.
+        let sides = match kind {
+            TableKind::Wdl if !material.symmetric => 2,
+            _ => 1,
+        };
+        let files = if material.has_pawns { 4 } else { 1 };
+        let both_pawns = material.has_pawns && material.pawn_counts[1] > 0;
+        let mut items: [[PairsData; 2]; 4] = Default::default();
+        let mut offset = 5;
+        for (file, tables) in items[..files].iter_mut().enumerate() {
+            let first = read_u8(&bytes, offset)?;
+            let second = match both_pawns {
+                true => read_u8(&bytes, offset + 1)?,
+                false => 0xff,
+            };
+            let orders = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            offset += 1 + both_pawns as usize;
+            for k in 0..material.piece_count {
+                let pieces = read_u8(&bytes, offset)?;
+                tables[0].pieces[k] = pieces & 0xf;
+                tables[1].pieces[k] = pieces >> 4;
+                offset += 1;
+            }
+            for (data, order) in tables[..sides].iter_mut().zip(orders) {
+                data.set_groups(material, order, file)?;
+            }
+        }
+        offset += offset & 1;
+
+        // The sizes, the sparse indices, the block lengths and the data of the tables each
+        // follow one another.
+        for data in tables_mut(&mut items, files, sides) {
+            offset = data.read_sizes(&bytes, offset)?;
+        }
+        let dtz_map = offset;
+        if kind == TableKind::Dtz {
+            let first_sides = items[..files].iter_mut().map(|tables| &mut tables[0]);
+            offset = read_dtz_map(first_sides, &bytes, offset)?;
+        }
+        for data in tables_mut(&mut items, files, sides) {
+            data.sparse_index = offset;
+            offset += 6 * data.sparse_index_size;
+        }
+        for data in tables_mut(&mut items, files, sides) {
+            data.block_lengths = offset;
+            offset += 2 * data.block_lengths_size;
+        }
+        if offset > bytes.len() {
+            return Err(Error::InvalidTablebase);
+        }
+        for data in tables_mut(&mut items, files, sides) {
+            offset = offset.next_multiple_of(64);
+            data.data = offset;
+            offset += data.blocks * data.block_size;
+        }
+        Ok(Self {
+            bytes,
+            items,
+            sides,
+            dtz_map,
+        })
+    }
+
+    /// Probes the value of `board`, with the colors swapped if `flip` is set. DTZ values
+    /// are for a position whose outcome is `wdl`.
+    fn probe(
+        &self,
+        board: &Board,
+        material: &Material,
+        flip: bool,
+        kind: TableKind,
+        wdl: Wdl,
+    ) -> Option<TableProbe> {
+        let (file, side, index) = self.index(board, material, flip)?;
+        if kind == TableKind::Dtz
+            && (self.items[file][0].flags & STM) as usize != side
+            && (material.has_pawns || !material.symmetric)
+        {
+            return Some(TableProbe::ChangeSideToMove);
+        }
+        let value = self.items[file][side % self.sides].decompress(&self.bytes, index)?;
+        self.map_score(file, value, kind, wdl)
+            .map(TableProbe::Value)
+    }
+
+    /// Returns the file of the leading pawn, the side to move and the index of `board` in
+    /// the table, with the colors swapped if `flip` is set.
+    fn index(&self, board: &Board, material: &Material, flip: bool) -> Option<(usize, usize, u64)> {
+        let indices = indices();
+        let side = flip as usize ^ (board.color_to_move() == Color::Black) as usize;
+        let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+        let mut squares = [0; MAX_PIECES];
+        let mut pieces = [0; MAX_PIECES];
+        let mut size = 0;
+
+        // Tables with pawns are split by the file of the leading pawn, the one of the
+        // leading color that comes last in the order of `map_pawns`.
+        let mut lead_pawns = 0;
+        let mut file = 0;
+        if material.has_pawns {
+            let color = match self.items[0][0].pieces[0] ^ flip_color {
+                code if code & 8 == 0 => Color::White,
+                _ => Color::Black,
+            };
+            lead_pawns = board.pieces(Piece::Pawn(color));
+            let mut pawns = lead_pawns;
+            while pawns != 0 {
+                *squares.get_mut(size)? = bits::pop_msb(&mut pawns) as usize ^ flip_squares;
+                size += 1;
+            }
+            let lead = (0..size).max_by_key(|&i| indices.map_pawns[squares[i]])?;
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+        let lead_count = size;
+
+        let mut others = board.occupancy() & !lead_pawns;
+        while others != 0 {
+            let square = bits::pop_msb(&mut others) as usize;
+            *squares.get_mut(size)? = square ^ flip_squares;
+            pieces[size] = piece_code(board.at(square)?) ^ flip_color;
+            size += 1;
+        }
+        let data = &self.items[file][side % self.sides];
+        // Puts the pieces in the order of the table.
+        for i in lead_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| data.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // The board is mirrored so the leading piece is on the queen side, and without
+        // pawns, in the a1-d1-d4 triangle.
+        let squares = &mut squares[..size];
+        if squares[0] % 8 > 3 {
+            for square in squares.iter_mut() {
+                *square ^= 7;
+            }
+        }
+        let mut index;
+        if material.has_pawns {
+            index = indices.lead_pawn_index[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|&square| indices.map_pawns[square]);
+            for (i, &square) in squares[..lead_count].iter().enumerate().skip(1) {
+                index += indices.binomial[i][indices.map_pawns[square]];
+            }
+        } else {
+            if squares[0] / 8 > 3 {
+                for square in squares.iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            // The first of the leading pieces off the diagonal is put below it.
+            for i in 0..data.group_length[0] {
+                match off_diagonal(squares[i]) {
+                    0 => continue,
+                    off if off > 0 => {
+                        for square in &mut squares[i..] {
+                            *square = flip_diagonal(*square);
+                        }
+                    }
+                    _ => {}
+                }
+                break;
+            }
+            index = if material.has_unique_pieces {
+                unique_pieces_index(indices, squares)? as u64
+            } else {
+                indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]] as u64
+            };
+        }
+
+        // The other groups are sets of squares, each one indexed among the squares the
+        // groups before it leave free.
+        index *= data.group_index[0];
+        let mut start = data.group_length[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_counts[1] > 0;
+        let mut next = 1;
+        while data.group_length[next] != 0 {
+            let end = start + data.group_length[next];
+            squares.get_mut(start..end)?.sort_unstable();
+            let mut group = 0;
+            for i in 0..end - start {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| square > s).count();
+                let free = (square - adjust).checked_sub(8 * remaining_pawns as usize)?;
+                group += indices.binomial[i + 1][free];
+            }
+            remaining_pawns = false;
+            index += group * data.group_index[next];
+            start = end;
+            next += 1;
+        }
+        Some((file, side, index))
+    }
+
+    /// Turns a decompressed value into a WDL value, or a DTZ value in plies.
+    fn map_score(&self, file: usize, value: u16, kind: TableKind, wdl: Wdl) -> Option<i32> {
+        if kind == TableKind::Wdl {
+            return Some(value as i32 - 2);
+        }
+        let data = &self.items[file][0];
+        let mut value = value as usize;
+        if data.flags & MAPPED != 0 {
+            // Values of the win, loss, cursed win and blessed loss maps.
+            let map = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+            let slot = data.map_index[map] + value;
+            value = match data.flags & WIDE != 0 {
+                true => u16::from_le_bytes(read(&self.bytes, self.dtz_map + 2 * slot)?) as usize,
+                false => read::<1>(&self.bytes, self.dtz_map + slot)?[0] as usize,
+            };
+        }
+        let plies = match wdl {
+            Wdl::Win => data.flags & WIN_PLIES != 0,
+            Wdl::Loss => data.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        let value = value as i32;
+        Some(if plies { value } else { 2 * value } + 1)
+    }
+}
+
+/// Returns the index of the first three pieces of a table where some side has a single
+/// piece of a type: the first one in the a1-d1-d4 triangle, the first off the diagonal below
+/// it, and none on the same square.
+fn unique_pieces_index(indices: &Indices, squares: &[usize]) -> Option<usize> {
+    let [first, second, third] = *squares.get(..3)? else {
+        return None;
+    };
+    let adjust1 = (second > first) as usize;
+    let adjust2 = (third > first) as usize + (third > second) as usize;
+    let rank = |square: usize| square / 8;
+    Some(if off_diagonal(first) != 0 {
+        (indices.map_a1d1d4[first] * 63 + second - adjust1) * 62 + third - adjust2
+    } else if off_diagonal(second) != 0 {
+        (6 * 63 + rank(first) * 28 + indices.map_b1h1h7[second]) * 62 + third - adjust2
+    } else if off_diagonal(third) != 0 {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + rank(first) * 7 * 28
+            + (rank(second).checked_sub(adjust1)?) * 28
+            + indices.map_b1h1h7[third]
+    } else {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + 4 * 7 * 28
+            + rank(first) * 7 * 6
+            + (rank(second).checked_sub(adjust1)?) * 6
+            + rank(third).checked_sub(adjust2)?
+    })
+}
+
+/// Returns the tables of `items` in the order of the files, for `files` files of the
+/// leading pawn and `sides` sides to move.
+fn tables_mut(
+    items: &mut [[PairsData; 2]; 4],
+    files: usize,
+    sides: usize,
+) -> impl Iterator<Item = &mut PairsData> {
+    items[..files]
+        .iter_mut()
+        .flat_map(move |tables| &mut tables[..sides])
+}
+
+/// Reads the maps of the DTZ values of `items`, returns the offset right after them.
+fn read_dtz_map<'a>(
+    items: impl Iterator<Item = &'a mut PairsData>,
+    bytes: &[u8],
+    mut offset: usize,
+) -> Result<usize> {
+    let start = offset;
+    for data in items {
+        if data.flags & MAPPED == 0 {
+            continue;
+        }
+        if data.flags & WIDE != 0 {
+            offset += offset & 1;
+            for index in &mut data.map_index {
+                *index = (offset - start) / 2 + 1;
+                offset += 2 * read_u16(bytes, offset)? as usize + 2;
+            }
+        } else {
+            for index in &mut data.map_index {
+                *index = offset - start + 1;
+                offset += read_u8(bytes, offset)? as usize + 1;
+            }
+        }
+    }
+    Ok(offset + (offset & 1))
+}
+
+/// A table file, loaded on first use.
+#[derive(Debug, Default)]
+struct TableFile {
+    path: Option<PathBuf>,
+    table: OnceLock<Option<Table>>,
+}
+
+impl TableFile {
+    /// Returns the table, or `None` if there is no file or it can't be read.
+    fn get(&self, material: &Material, kind: TableKind) -> Option<&Table> {
+        let path = self.path.as_ref()?;
+        self.table
+            .get_or_init(|| {
+                let bytes = std::fs::read(path).ok()?;
+                Table::parse(bytes, material, kind).ok()
+            })
+            .as_ref()
+    }
+}
+
+/// The files of one material.
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    material: Material,
+    wdl: TableFile,
+    dtz: TableFile,
+}
+
+/// The tablebase files of a directory.
+///
+/// Files are only read when a position of their material is first probed, and then kept in
+/// memory. Tablebases can be probed from several threads at once.
+#[derive(Debug, Default)]
+pub struct Tablebases {
+    entries: Vec<Entry>,
+    /// Index in `entries` of the files of each material, named with white first and with
+    /// the colors swapped.
+    keys: HashMap<String, usize>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    /// Finds the `.rtbw` and `.rtbz` files in `directory`. Other files are ignored.
+    pub fn open(directory: impl AsRef<Path>) -> Result<Self> {
+        let mut tablebases = Self::default();
+        for file in std::fs::read_dir(directory)? {
+            let path = file?.path();
+            let kind = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("rtbw") => TableKind::Wdl,
+                Some("rtbz") => TableKind::Dtz,
+                _ => continue,
+            };
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let Some(material) = Material::from_name(name) else {
+                continue;
+            };
+            let index = match tablebases.keys.get(name) {
+                Some(&index) if tablebases.entries[index].name == name => index,
+                _ => tablebases.add(name, material),
+            };
+            let entry = &mut tablebases.entries[index];
+            match kind {
+                TableKind::Wdl => {
+                    entry.wdl.path = Some(path);
+                    tablebases.max_pieces = tablebases.max_pieces.max(entry.material.piece_count);
+                }
+                TableKind::Dtz => entry.dtz.path = Some(path),
+            }
+        }
+        Ok(tablebases)
+    }
+
+    fn add(&mut self, name: &str, material: Material) -> usize {
+        let index = self.entries.len();
+        self.entries.push(Entry {
+            name: name.to_string(),
+            material,
+            wdl: TableFile::default(),
+            dtz: TableFile::default(),
+        });
+        self.keys.insert(name.to_string(), index);
+        if let Some((white, black)) = name.split_once('v') {
+            self.keys
+                .entry(format!("{}v{}", black, white))
+                .or_insert(index);
+        }
+        index
+    }
+
+    /// Returns the number of materials with a WDL table.
+    pub fn len(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.wdl.path.is_some())
+            .count()
+    }
+
+    /// Returns `true` if there are no WDL tables.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of pieces, kings included, of the largest WDL table.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Returns `true` if `board` has few enough pieces and no castling rights to be probed.
+    /// Probes may still fail if the table of its material is missing.
+    pub fn covers(&self, board: &Board) -> bool {
+        board.castling_rights() == CastleRights::None
+            && bits::count_ones(board.occupancy()) as usize <= self.max_pieces
+    }
+
+    /// Returns the outcome of `board` under perfect play, or `None` if the tables don't
+    /// tell.
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if !self.covers(board) {
+            return None;
+        }
+        self.search(&mut board.clone(), false).map(|(wdl, _)| wdl)
+    }
+
+    /// Returns the distance to zeroing of `board`: the number of plies to the next capture
+    /// or pawn move with perfect play, positive when winning and negative when losing, 100
+    /// more for the wins and losses the fifty-move rule draws, and 0 for draws. Returns
+    /// `None` if the tables don't tell.
+    ///
+    /// The distance may be one ply more than the real one, but never on the right side of
+    /// the fifty-move rule when it's not.
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        if !self.covers(board) {
+            return None;
+        }
+        self.dtz(&mut board.clone())
+    }
+
+    /// Returns the legal moves of `board` that keep the best outcome the tables give,
+    /// counting the fifty-move rule from the halfmove clock. Winning moves are all kept while
+    /// the rule is out of reach, otherwise only those that zero the clock the soonest.
+    /// Returns `None` if the tables don't tell.
+    ///
+    /// DTZ tables are used when found for every move, otherwise WDL tables.
+    pub fn root_moves(&self, board: &Board) -> Option<Vec<Move>> {
+        if !self.covers(board) {
+            return None;
+        }
+        let mut board = board.clone();
+        let ranks = self
+            .rank_by_dtz(&mut board)
+            .or_else(|| self.rank_by_wdl(&mut board))?;
+        let best = ranks.iter().map(|&(_, rank)| rank).max()?;
+        Some(
+            ranks
+                .into_iter()
+                .filter(|&(_, rank)| rank == best)
+                .map(|(mov, _)| mov)
+                .collect(),
+        )
+    }
+
+    /// Ranks the legal moves of `board` by the distance to zeroing they lead to.
+    fn rank_by_dtz(&self, board: &mut Board) -> Option<Vec<(Move, i32)>> {
+        let halfmove_clock = board.halfmove_clock() as i32;
+        let mut ranks = Vec::new();
+        for mov in board.get_legal_moves() {
+            let undo = board.make_move(mov);
+            let dtz = if board.halfmove_clock() == 0 {
+                self.search(board, false)
+                    .map(|(wdl, _)| dtz_before_zeroing(-wdl))
+            } else if board.halfmove_clock() >= 100 {
+                Some(0)
+            } else {
+                self.dtz(board).map(|dtz| match -dtz {
+                    dtz if dtz > 0 => dtz + 1,
+                    dtz if dtz < 0 => dtz - 1,
+                    _ => 0,
+                })
+            };
+            // A mate is reached on the move.
+            let dtz = match dtz {
+                Some(2) if is_mate(board) => Some(1),
+                dtz => dtz,
+            };
+            board.undo_move(mov, undo);
+            let dtz = dtz?;
+            let rank = if dtz > 0 {
+                match dtz + halfmove_clock <= 99 {
+                    true => MAX_DTZ,
+                    false => MAX_DTZ / 2 - (dtz + halfmove_clock),
+                }
+            } else if dtz < 0 {
+                match -dtz * 2 + halfmove_clock < 100 {
+                    true => -MAX_DTZ,
+                    false => -MAX_DTZ / 2 + (-dtz + halfmove_clock),
+                }
+            } else {
+                0
+            };
+            ranks.push((mov, rank));
+        }
+        Some(ranks)
+    }
+
+    /// Ranks the legal moves of `board` by the outcome they lead to.
+    fn rank_by_wdl(&self, board: &mut Board) -> Option<Vec<(Move, i32)>> {
+        let mut ranks = Vec::new();
+        for mov in board.get_legal_moves() {
+            let undo = board.make_move(mov);
+            let wdl = self.search(board, false);
+            board.undo_move(mov, undo);
+            let rank = match -wdl?.0 {
+                Wdl::Win => MAX_DTZ,
+                Wdl::CursedWin => MAX_DTZ - 101,
+                Wdl::Draw => 0,
+                Wdl::BlessedLoss => -MAX_DTZ + 101,
+                Wdl::Loss => -MAX_DTZ,
+            };
+            ranks.push((mov, rank));
+        }
+        Some(ranks)
+    }
+
+    /// Returns the outcome of `board`, found by playing the captures, and the pawn moves
+    /// too if `zeroing_moves` is set, before probing the WDL table. Also returns `true` if
+    /// the outcome comes from one of those moves.
+    fn search(&self, board: &mut Board, zeroing_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = board.get_legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for &mov in &moves {
+            if !mov.is_capture() && !(zeroing_moves && is_pawn_move(board, mov)) {
+                continue;
+            }
+            searched += 1;
+            let undo = board.make_move(mov);
+            let result = self.search(board, false);
+            board.undo_move(mov, undo);
+            let wdl = -result?.0;
+            if wdl > best {
+                best = wdl;
+                if wdl == Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+
+        // The table may store anything for positions where a capture is best, or with an
+        // en passant capture, so it's not probed when every move was played.
+        let all_searched = searched > 0 && searched == moves.len();
+        let stored = match all_searched {
+            true => best,
+            false => match self.probe_table(board, TableKind::Wdl, Wdl::Draw)? {
+                TableProbe::Value(value) => Wdl::from_value(value),
+                TableProbe::ChangeSideToMove => return None,
+            },
+        };
+        if best >= stored {
+            return Some((best, best > Wdl::Draw || all_searched));
+        }
+        Some((stored, false))
+    }
+
+    /// Returns the distance to zeroing of `board`, see `probe_dtz`.
+    fn dtz(&self, board: &mut Board) -> Option<i32> {
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        let sign = (wdl as i32).signum();
+        match self.probe_table(board, TableKind::Dtz, wdl)? {
+            TableProbe::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+                return Some((dtz + if cursed { 100 } else { 0 }) * sign);
+            }
+            TableProbe::ChangeSideToMove => {}
+        }
+
+        // The table stores the other side to move: the distance is that of the best move.
+        let mut best = None;
+        for mov in board.get_legal_moves() {
+            let zeroing = mov.is_capture() || is_pawn_move(board, mov);
+            let undo = board.make_move(mov);
+            let dtz = match zeroing {
+                // The distance of a zeroing move is counted before it is played.
+                true => self
+                    .search(board, false)
+                    .map(|(wdl, _)| -dtz_before_zeroing(wdl)),
+                false => self.dtz(board).map(|dtz| -dtz),
+            };
+            let mate = is_mate(board);
+            board.undo_move(mov, undo);
+            let mut dtz = dtz?;
+            if dtz == 1 && mate {
+                best = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz.signum() == sign && best.is_none_or(|best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        // Without legal moves, the side to move is mated.
+        Some(best.unwrap_or(-1))
+    }
+
+    /// Probes the table of `board` of `kind`, DTZ values being for a position whose outcome
+    /// is `wdl`.
+    fn probe_table(&self, board: &Board, kind: TableKind, wdl: Wdl) -> Option<TableProbe> {
+        if bits::count_ones(board.occupancy()) == 2 {
+            return Some(TableProbe::Value(0));
+        }
+        let key = format!(
+            "{}v{}",
+            material_name(board, Color::White),
+            material_name(board, Color::Black)
+        );
+        let entry = &self.entries[*self.keys.get(&key)?];
+        let table = match kind {
+            TableKind::Wdl => entry.wdl.get(&entry.material, kind)?,
+            TableKind::Dtz => entry.dtz.get(&entry.material, kind)?,
+        };
+        // Tables have the side named first as white and, when both sides have the same
+        // pieces, white to move.
+        let flip = match entry.material.symmetric {
+            true => board.color_to_move() == Color::Black,
+            false => entry.name != key,
+        };
+        table.probe(board, &entry.material, flip, kind, wdl)
+    }
+}
+
+#[test]
+fn index_tables() {
+    let indices = indices();
+    assert_eq!(indices.map_kk.iter().flatten().max(), Some(&461));
+    assert_eq!((indices.map_a1d1d4[1], indices.map_a1d1d4[27]), (0, 9));
+    assert_eq!((indices.map_pawns[8], indices.map_pawns[15]), (47, 46));
+    assert_eq!((indices.map_pawns[16], indices.map_pawns[52]), (45, 0));
+    assert_eq!(
+        (indices.binomial[2][5], indices.binomial[5][63]),
+        (10, 7_028_847)
+    );
+    assert_eq!(indices.lead_pawns_size[1], [6; 4]);
+    // Three pieces with one of them in the triangle
+    assert_eq!(unique_pieces_index(indices, &[27, 63, 54]), Some(31_331));
+
+    assert_eq!(
+        Material::from_name("KRPvKR"),
+        Some(Material {
+            piece_count: 5,
+            has_pawns: true,
+            has_unique_pieces: true,
+            pawn_counts: [1, 0],
+            symmetric: false,
+        })
+    );
+    let material = Material::from_name("KPPvKP").unwrap();
+    assert_eq!(
+        (material.pawn_counts, material.has_unique_pieces),
+        ([1, 2], true)
+    );
+    assert!(!Material::from_name("KNNvK").unwrap().has_unique_pieces);
+    for name in ["KRvK.rtbw", "KRK", "RKvK", "KvKK", "KQQQvKQQQ", "KXvK"] {
+        assert_eq!(Material::from_name(name), None);
+    }
+}
+
+#[test]
+fn single_value_tables() {
+    use crate::parser::load_position_from_fen;
+    use crate::search::{Limits, Searcher};
+
+    // Tables where every position has the same value: KQvK is won with white to move and
+    // lost with black to move, 5 moves from zeroing.
+    // Removed when the test ends, even if an assertion fails.
+    struct TempDir(std::path::PathBuf);
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+    let temp_dir = TempDir(std::env::temp_dir().join(format!("syzygy-{}", std::process::id())));
+    let directory = &temp_dir.0;
+    std::fs::create_dir_all(directory).unwrap();
+    let file = |name: &str, bytes: &[&[u8]]| std::fs::write(directory.join(name), bytes.concat());
+    // Pieces are the white king, queen and black king, for both sides to move.
+    file(
+        "KQvK.rtbw",
+        &[
+            &WDL_MAGIC,
+            &[SPLIT, 0, 0x66, 0x55, 0xee, 0],
+            &[SINGLE_VALUE, 4, SINGLE_VALUE, 0],
+        ],
+    )
+    .unwrap();
+    file(
+        "KQvK.rtbz",
+        &[
+            &DTZ_MAGIC,
+            &[SPLIT, 0, 0x06, 0x05, 0x0e, 0],
+            &[SINGLE_VALUE, 5],
+        ],
+    )
+    .unwrap();
+    file("KRvK.rtbw", &[&WDL_MAGIC, &[SPLIT]]).unwrap();
+    file("KQvK.txt", &[]).unwrap();
+    let tablebases = Tablebases::open(directory).unwrap();
+    assert_eq!((tablebases.len(), tablebases.max_pieces()), (2, 3));
+
+    let board = |fen| load_position_from_fen(fen).unwrap();
+    for (fen, wdl, dtz) in [
+        ("7k/8/8/8/8/8/8/KQ6 w - - 0 1", Wdl::Win, 11),
+        // The other side to move is found by playing every move
+        ("7k/8/8/8/8/8/8/KQ6 b - - 0 1", Wdl::Loss, -12),
+        // Colors swapped
+        ("kq6/8/8/8/8/8/8/7K b - - 0 1", Wdl::Win, 11),
+        ("kq6/8/8/8/8/8/8/7K w - - 0 1", Wdl::Loss, -12),
+        // Captures are played before probing
+        ("8/8/8/8/8/8/6Qk/K7 b - - 0 1", Wdl::Draw, 0),
+    ] {
+        assert_eq!(tablebases.probe_wdl(&board(fen)), Some(wdl), "{}", fen);
+        assert_eq!(tablebases.probe_dtz(&board(fen)), Some(dtz), "{}", fen);
+    }
+    // Missing, corrupted or not covered
+    for fen in [
+        "7k/8/8/8/8/8/8/KR6 w - - 0 1",
+        "7k/8/8/8/8/8/8/KN6 w - - 0 1",
+        "7k/8/8/8/8/8/8/KQR5 w - - 0 1",
+    ] {
+        assert_eq!(tablebases.probe_wdl(&board(fen)), None, "{}", fen);
+    }
+
+    // Root moves that hang the queen are left out
+    let board = board("7k/8/8/8/8/8/8/KQ6 w - - 0 1");
+    let moves = tablebases.root_moves(&board).unwrap();
+    let names: Vec<_> = moves.iter().map(|mov| mov.to_string()).collect();
+    assert!(names.contains(&"b1b7".to_string()) && names.contains(&"a1a2".to_string()));
+    assert!(!names.contains(&"b1h7".to_string()));
+    assert!(moves.len() < board.get_legal_moves().len());
+
+    let mut searcher = Searcher::new();
+    searcher.set_tablebases(Some(tablebases));
+    let limits = Limits {
+        depth: Some(4),
+        ..Default::default()
+    };
+    let result = searcher.search(&board, &limits);
+    assert!(moves.contains(&result.best_move.unwrap()));
+    assert!(result
+        .lines
+        .iter()
+        .all(|line| moves.contains(&line.moves[0])));
+}
+
+#[test]
+fn compressed_tables() {
+    use crate::parser::load_position_from_fen;
+
+    // KQvK with white to move Huffman-coded in 79 blocks of 64 bytes, each one 100 times the
+    // symbols 2, 0 and 1 coded 1, 00 and 01, except the last with 33. Symbol 2 is the pair of
+    // symbols 3 and 0, so every 4 positions are a draw, 2 wins and a loss.
+    let sizes: &[u8] = &[0, 6, 10, 0, 79, 0, 0, 0, 2, 1, 2, 0, 0, 0, 4, 0];
+    let btree: &[u8] = &[4, 0xf0, 0xff, 0, 0xf0, 0xff, 3, 0, 0, 2, 0xf0, 0xff];
+    let values = [2, 4, 4, 0];
+    let mut sparse_index = Vec::new();
+    for k in 0..31u32 {
+        // Every entry is for the middle of its span.
+        let index = k * 1024 + 512;
+        sparse_index.extend((index / 400).to_le_bytes());
+        sparse_index.extend(((index % 400) as u16).to_le_bytes());
+    }
+    let mut block_lengths = Vec::new();
+    let mut blocks = Vec::new();
+    for block in 0..79 {
+        let patterns = if block < 78 { 100 } else { 33 };
+        block_lengths.extend((4 * patterns as u16 - 1).to_le_bytes());
+        let mut bytes = [0; 64];
+        for i in 0..patterns {
+            bytes[5 * i / 8] |= 0x80 >> (5 * i % 8);
+            bytes[(5 * i + 4) / 8] |= 0x80 >> ((5 * i + 4) % 8);
+        }
+        blocks.extend(bytes);
+    }
+    let bytes = [
+        &WDL_MAGIC,
+        &[SPLIT, 0, 0x66, 0x55, 0xee, 0][..],
+        sizes,
+        btree,
+        &[SINGLE_VALUE, 2],
+        &sparse_index,
+        &block_lengths,
+        &blocks,
+    ]
+    .concat();
+    let material = Material::from_name("KQvK").unwrap();
+    let table = Table::parse(bytes, &material, TableKind::Wdl).unwrap();
+    assert_eq!(table.items[0][0].data, 384);
+    for index in 0..31_332 {
+        let value = table.items[0][0].decompress(&table.bytes, index);
+        assert_eq!(value, Some(values[index as usize % 4]), "{}", index);
+        assert_eq!(table.items[0][1].decompress(&table.bytes, index), Some(2));
+    }
+    assert_eq!(
+        table.items[0][0].decompress(&table.bytes, 31_332 + 1024),
+        None
+    );
+    for (fen, side) in [
+        ("7k/8/8/8/8/8/8/KQ6 w - - 0 1", 0),
+        ("7k/8/8/8/8/8/8/KQ6 b - - 0 1", 1),
+    ] {
+        let board = load_position_from_fen(fen).unwrap();
+        let (file, probed_side, index) = table.index(&board, &material, false).unwrap();
+        assert_eq!((file, probed_side), (0, side));
+        let wdl = if side == 0 {
+            values[index as usize % 4] - 2
+        } else {
+            0
+        };
+        let probe = table.probe(&board, &material, false, TableKind::Wdl, Wdl::Draw);
+        assert_eq!(probe, Some(TableProbe::Value(wdl as i32)), "{}", fen);
+    }
+
+    // DTZ tables whose values are the second of the values of each outcome, in plies for
+    // losses, stored on a byte or two.
+    for (wide, map) in [
+        (0, &[2u16, 7, 8, 2, 9, 10, 2, 60, 61, 2, 70, 71]),
+        (WIDE, &[2, 700, 800, 2, 9, 10, 2, 60, 61, 2, 70, 71]),
+    ] {
+        let map: Vec<u8> = match wide {
+            0 => map.iter().map(|&value| value as u8).collect(),
+            _ => map.iter().flat_map(|&value| value.to_le_bytes()).collect(),
+        };
+        let bytes = [
+            &DTZ_MAGIC,
+            &[SPLIT, 0, 0x06, 0x05, 0x0e, 0][..],
+            &[SINGLE_VALUE | MAPPED | LOSS_PLIES | wide, 1],
+            &map,
+        ]
+        .concat();
+        let table = Table::parse(bytes, &material, TableKind::Dtz).unwrap();
+        let win = if wide == 0 { 17 } else { 1601 };
+        for (wdl, dtz) in [
+            (Wdl::Win, win),
+            (Wdl::Loss, 11),
+            (Wdl::CursedWin, 123),
+            (Wdl::BlessedLoss, 143),
+        ] {
+            assert_eq!(table.map_score(0, 1, TableKind::Dtz, wdl), Some(dtz));
+        }
+    }
+}
+
+#[test]
+fn table_indices() {
+    use std::collections::HashSet;
+
+    // Indices must be the same for positions the symmetries of the table turn into each
+    // other, different for the others, and below the size of the table.
+    let check = |name: &str, files: &[u8], positions: Vec<Vec<(Piece, usize)>>| {
+        let material = Material::from_name(name).unwrap();
+        let (header, files_count) = match material.has_pawns {
+            true => (HAS_PAWNS, 4),
+            false => (0, 1),
+        };
+        let header = header | if material.symmetric { 0 } else { SPLIT };
+        let sides = if material.symmetric { 1 } else { 2 };
+        let mut bytes = [&WDL_MAGIC, &[header][..], files].concat();
+        bytes.resize(bytes.len() + bytes.len() % 2, 0);
+        for _ in 0..files_count * sides {
+            bytes.extend([SINGLE_VALUE, 2]);
+        }
+        let table = Table::parse(bytes, &material, TableKind::Wdl).unwrap();
+        let mirrors: &[fn(usize) -> usize] = match material.has_pawns {
+            true => &[|square| square, |square| square ^ 7],
+            false => &[
+                |square| square,
+                |square| square ^ 7,
+                |square| square ^ 56,
+                |square| square ^ 63,
+                flip_diagonal,
+                |square| flip_diagonal(square) ^ 7,
+                |square| flip_diagonal(square) ^ 56,
+                |square| flip_diagonal(square) ^ 63,
+            ],
+        };
+        let board = |pieces: &[(Piece, usize)], color| {
+            let mut array = [None; 64];
+            for &(piece, square) in pieces {
+                array[square] = Some(piece);
+            }
+            Board::from_array(&array, CastleRights::None, color, None, 0, 1)
+        };
+
+        let mut indices = HashSet::new();
+        let mut classes = HashSet::new();
+        for pieces in positions {
+            let (file, side, index) = table
+                .index(&board(&pieces, Color::White), &material, false)
+                .unwrap();
+            let data = &table.items[file][side % table.sides];
+            let groups = data.group_length.iter().position(|&length| length == 0);
+            assert!(index < data.group_index[groups.unwrap()], "{:?}", pieces);
+            for mirror in mirrors {
+                let mirrored: Vec<_> = pieces.iter().map(|&(p, s)| (p, mirror(s))).collect();
+                let mirrored = table.index(&board(&mirrored, Color::White), &material, false);
+                assert_eq!(mirrored, Some((file, side, index)), "{:?}", pieces);
+            }
+            let swapped: Vec<_> = pieces
+                .iter()
+                .map(|&(piece, square)| {
+                    (Piece::new(piece.piece_type(), !piece.color()), square ^ 56)
+                })
+                .collect();
+            let swapped = table.index(&board(&swapped, Color::Black), &material, true);
+            assert_eq!(swapped, Some((file, side, index)), "{:?}", pieces);
+
+            indices.insert((file, index));
+            let class = mirrors.iter().map(|mirror| {
+                let mut squares: Vec<_> = pieces
+                    .iter()
+                    .map(|&(piece, square)| (piece_code(piece), mirror(square)))
+                    .collect();
+                squares.sort_unstable();
+                squares
+            });
+            classes.insert(class.min().unwrap());
+        }
+        assert_eq!(indices.len(), classes.len(), "{}", name);
+    };
+
+    let apart = |a: usize, b: usize| (a % 8).abs_diff(b % 8).max((a / 8).abs_diff(b / 8)) > 1;
+    let kings = || {
+        (0..64).flat_map(move |white| {
+            (0..64)
+                .filter(move |&black| apart(white, black))
+                .map(move |black| {
+                    vec![
+                        (Piece::King(Color::White), white),
+                        (Piece::King(Color::Black), black),
+                    ]
+                })
+        })
+    };
+    let with = |pieces: &[(Piece, usize)], squares: &[usize], piece: Piece| {
+        squares
+            .iter()
+            .filter(|&&square| pieces.iter().all(|&(_, s)| s != square))
+            .map(|&square| [pieces, &[(piece, square)]].concat())
+            .collect::<Vec<_>>()
+    };
+    let pawn_squares: Vec<_> = (8..56).collect();
+
+    let positions = kings()
+        .flat_map(|pieces| with(&pieces, &[0, 9, 12, 27, 38, 57], Piece::Rook(Color::White)))
+        .collect();
+    check("KRvK", &[0, 0x66, 0x44, 0xee], positions);
+
+    // Positions with both kings on a long diagonal are stored twice, once with the knights
+    // mirrored across it.
+    let diagonal = |square: usize| off_diagonal(square) == 0 || square % 8 + square / 8 == 7;
+    let positions = kings()
+        .filter(|pieces| !(diagonal(pieces[0].1) && diagonal(pieces[1].1)))
+        .flat_map(|pieces| {
+            let knights = [[1, 6], [18, 45], [20, 21]];
+            knights.into_iter().filter_map(move |squares| {
+                let knights = squares.map(|square| (Piece::Knight(Color::White), square));
+                let free = knights
+                    .iter()
+                    .all(|&(_, s)| pieces.iter().all(|&(_, k)| k != s));
+                free.then(|| [&pieces[..], &knights].concat())
+            })
+        })
+        .collect();
+    check("KNNvK", &[0, 0x66, 0xee, 0x22, 0x22], positions);
+
+    let positions = kings()
+        .filter(|pieces| [0, 7, 36, 60].contains(&pieces[1].1))
+        .flat_map(|pieces| with(&pieces, &pawn_squares, Piece::Pawn(Color::White)))
+        .collect();
+    let file = [0x00, 0x11, 0x66, 0xee];
+    check("KPvK", &file.repeat(4), positions);
+
+    let positions = kings()
+        .filter(|pieces| pieces[0].1 < 8 && [59, 63].contains(&pieces[1].1))
+        .flat_map(|pieces| with(&pieces, &pawn_squares, Piece::Pawn(Color::White)))
+        .flat_map(|pieces| with(&pieces, &pawn_squares, Piece::Pawn(Color::Black)))
+        .collect();
+    let file = [0x00, 0x11, 0x11, 0x99, 0x66, 0xee];
+    check("KPvKP", &file.repeat(4), positions);
+}
+
+#[test]
+fn syzygy_tables() {
+    use crate::parser::load_position_from_fen;
+
+    let tablebases =
+        Tablebases::open(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/syzygy")).unwrap();
+    assert_eq!(tablebases.max_pieces(), 3);
+    let board = |fen| load_position_from_fen(fen).unwrap();
+    for (fen, wdl) in [
+        ("7k/8/8/8/8/8/8/KQ6 w - - 0 1", Wdl::Win),
+        ("7k/8/8/8/8/8/8/KQ6 b - - 0 1", Wdl::Loss),
+        ("8/8/8/8/8/8/6Rk/K7 b - - 0 1", Wdl::Draw),
+        ("k7/8/8/8/8/8/P7/K7 w - - 0 1", Wdl::Draw),
+        ("8/8/8/8/8/8/8/KN5k w - - 0 1", Wdl::Draw),
+        ("8/8/8/8/8/8/8/KB5k b - - 0 1", Wdl::Draw),
+        ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+        ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+        ("8/8/8/8/3p4/3k4/8/3K4 w - - 0 1", Wdl::Loss),
+        ("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1", Wdl::Draw),
+    ] {
+        assert_eq!(tablebases.probe_wdl(&board(fen)), Some(wdl), "{}", fen);
+        let dtz = tablebases.probe_dtz(&board(fen)).unwrap();
+        assert_eq!(dtz.signum(), (wdl as i32).signum(), "{}", fen);
+    }
+
+    // Mate in one
+    let board = board("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1");
+    assert_eq!(tablebases.probe_dtz(&board), Some(1));
+    let moves = tablebases.root_moves(&board).unwrap();
+    assert!(moves.contains(&board.move_from_notation("b1b8").unwrap()));
+}
@@ -0,0 +1,216 @@
+//! Time management.
+//!
+//! `TimeManager` splits the time left on the clock into two limits for the current move. The
+//! search always stops at the hard limit, even in the middle of an iteration. The soft limit
+//! is only checked between iterations, and moves further out while the search is unsure:
+//! when the best move keeps changing or the score drops.
+//!
+//! The manager never reads the clock itself, it's told how much time has passed, so it can
+//! be driven by a simulated clock.
+
+use crate::bitboard::Move;
+use crate::piece::Color;
+use std::time::Duration;
+
+/// Time kept aside on every move for communication with the interface.
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+/// Number of moves the remaining time is split over when the time control doesn't say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// How many times the soft limit the hard limit is, time on the clock allowing.
+const HARD_LIMIT_FACTOR: u32 = 4;
+/// A score drop of at least this many centipawns since the last iteration extends the time.
+const SCORE_DROP: i32 = 30;
+
+/// The state of the clocks, as sent by UCI interfaces with `go`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Clock {
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Duration,
+    pub binc: Duration,
+    /// Moves left until the next time control, if the time control has any.
+    pub movestogo: Option<u32>,
+    /// Fixed time to spend on the move, overriding the clocks.
+    pub movetime: Option<Duration>,
+}
+
+/// Decides how long to search the current move for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeManager {
+    soft: Duration,
+    hard: Duration,
+    best_move: Option<Move>,
+    score: Option<i32>,
+    /// Grows every time the best move changes and halves every iteration.
+    instability: u32,
+    score_dropped: bool,
+}
+
+impl TimeManager {
+    /// Returns a time manager for `color` to move with `clock`, or `None` if the clock doesn't
+    /// limit the time of `color`.
+    pub fn new(clock: &Clock, color: Color) -> Option<Self> {
+        let (soft, hard) = match clock.movetime {
+            Some(movetime) => {
+                let time = movetime.saturating_sub(MOVE_OVERHEAD);
+                (time, time)
+            }
+            None => {
+                let (time, increment) = match color {
+                    Color::White => (clock.wtime?, clock.winc),
+                    Color::Black => (clock.btime?, clock.binc),
+                };
+                let moves_to_go = clock.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+                let left = time.saturating_sub(MOVE_OVERHEAD);
+                // Some time is kept for the next moves, unless this is the last one before
+                // the time control.
+                let max = match moves_to_go {
+                    1 => left,
+                    _ => left * 3 / 4,
+                };
+                let soft = (time / moves_to_go + increment * 3 / 4).min(max);
+                (soft, (soft * HARD_LIMIT_FACTOR).min(max))
+            }
+        };
+        Some(Self {
+            soft,
+            hard,
+            best_move: None,
+            score: None,
+            instability: 0,
+            score_dropped: false,
+        })
+    }
+
+    /// Returns the time after which the search stops between iterations, before extensions.
+    pub fn soft_limit(&self) -> Duration {
+        self.soft
+    }
+
+    /// Returns the time after which the search stops right away.
+    pub fn hard_limit(&self) -> Duration {
+        self.hard
+    }
+
+    /// Records the result of a completed iteration.
+    pub fn update(&mut self, best_move: Option<Move>, score: i32) {
+        self.instability /= 2;
+        if self.best_move.is_some() && best_move != self.best_move {
+            self.instability += 2;
+        }
+        self.score_dropped = self
+            .score
+            .is_some_and(|previous| previous - score >= SCORE_DROP);
+        self.best_move = best_move;
+        self.score = Some(score);
+    }
+
+    /// Returns the soft limit extended according to the results seen so far.
+    pub fn extended_limit(&self) -> Duration {
+        let mut percent = 100 + 25 * self.instability;
+        if self.score_dropped {
+            percent += 50;
+        }
+        (self.soft * percent / 100).min(self.hard)
+    }
+
+    /// Returns `true` if no other iteration should be started after `elapsed`.
+    pub fn should_stop(&self, elapsed: Duration) -> bool {
+        elapsed >= self.extended_limit()
+    }
+}
+
+#[test]
+fn allocation() {
+    let ms = Duration::from_millis;
+    let clock = Clock {
+        wtime: Some(ms(60_000)),
+        btime: Some(ms(6_000)),
+        winc: ms(1_000),
+        ..Default::default()
+    };
+    let white = TimeManager::new(&clock, Color::White).unwrap();
+    assert_eq!(white.soft_limit(), ms(2_000 + 750));
+    assert_eq!(white.hard_limit(), ms(11_000));
+    let black = TimeManager::new(&clock, Color::Black).unwrap();
+    assert_eq!((black.soft_limit(), black.hard_limit()), (ms(200), ms(800)));
+
+    // A quarter of the clock is kept for the next moves
+    let clock = Clock {
+        wtime: Some(ms(10_050)),
+        movestogo: Some(2),
+        ..Default::default()
+    };
+    let white = TimeManager::new(&clock, Color::White).unwrap();
+    assert_eq!(
+        (white.soft_limit(), white.hard_limit()),
+        (ms(5_025), ms(7_500))
+    );
+
+    // The last move before the time control may use the whole clock
+    let clock = Clock {
+        wtime: Some(ms(10_000)),
+        movestogo: Some(1),
+        ..Default::default()
+    };
+    let white = TimeManager::new(&clock, Color::White).unwrap();
+    assert_eq!(white.hard_limit(), ms(9_950));
+    assert_eq!(TimeManager::new(&clock, Color::Black), None);
+
+    let clock = Clock {
+        movetime: Some(ms(1_000)),
+        ..clock
+    };
+    let white = TimeManager::new(&clock, Color::White).unwrap();
+    assert_eq!((white.soft_limit(), white.hard_limit()), (ms(950), ms(950)));
+}
+
+#[test]
+fn extensions() {
+    let ms = Duration::from_millis;
+    let clock = Clock {
+        wtime: Some(ms(30_000)),
+        ..Default::default()
+    };
+    let mut manager = TimeManager::new(&clock, Color::White).unwrap();
+    let e4 = Move::from_notation("e2e4").ok();
+    let d4 = Move::from_notation("d2d4").ok();
+
+    // A stable search stops at the soft limit
+    for score in [20, 25, 22] {
+        manager.update(e4, score);
+    }
+    assert!(!manager.should_stop(ms(999)));
+    assert!(manager.should_stop(ms(1_000)));
+
+    // A new best move extends the time, less and less as it stays the best
+    manager.update(d4, 22);
+    assert_eq!(manager.extended_limit(), ms(1_500));
+    assert!(!manager.should_stop(ms(1_200)));
+    manager.update(d4, 22);
+    assert_eq!(manager.extended_limit(), ms(1_250));
+    manager.update(d4, 22);
+    assert_eq!(manager.extended_limit(), ms(1_000));
+
+    // So does a falling score
+    manager.update(d4, -20);
+    assert_eq!(manager.extended_limit(), ms(1_500));
+    manager.update(d4, -25);
+    assert_eq!(manager.extended_limit(), ms(1_000));
+
+    // The best move changing every iteration never goes past the hard limit
+    for i in 0..10 {
+        manager.update([e4, d4][i % 2], 0);
+    }
+    assert_eq!(manager.extended_limit(), ms(1_750));
+    let clock = Clock {
+        wtime: Some(ms(4_050)),
+        movestogo: Some(2),
+        ..Default::default()
+    };
+    let mut manager = TimeManager::new(&clock, Color::White).unwrap();
+    for i in 0..10 {
+        manager.update([e4, d4][i % 2], 0);
+    }
+    assert_eq!(manager.extended_limit(), ms(3_000));
+}
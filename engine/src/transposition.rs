@@ -0,0 +1,291 @@
+//! Transposition table.
+//!
+//! The table caches the results of searched positions, indexed by their Zobrist hash, so a
+//! position reached again through another move order doesn't have to be searched twice.
+//!
+//! Entries are grouped in buckets of two slots. The first slot keeps the deepest search of
+//! the positions mapped to the bucket, the second one always takes the latest entry that
+//! didn't fit in the first. Entries left by previous searches are replaced before any
+//! other, no matter their depth.
+//!
+//! Slots are made of atomics so the table can be shared between threads. Each slot stores
+//! the key XOR-ed with its data, so a slot written by two threads at once fails the key
+//! check instead of returning mixed up data.
+
+use crate::bitboard::Move;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// Size of the table, in megabytes, when none is given.
+pub const DEFAULT_SIZE_MB: usize = 16;
+
+/// How the stored score relates to the real score of the position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact.
+    Exact,
+    /// The real score is at least the stored one, the search failed high.
+    Lower,
+    /// The real score is at most the stored one, the search failed low.
+    Upper,
+}
+
+/// The information stored for a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The best move found, if the search got to try any move.
+    pub best_move: Option<Move>,
+    pub score: i32,
+    /// Remaining depth the position was searched to.
+    pub depth: u8,
+    pub bound: Bound,
+}
+
+impl Entry {
+    /// Packs the entry in 64 bits, along with the generation it was stored in. The highest
+    /// bit is always set so that no entry is packed as 0, which marks empty slots.
+    fn to_bits(self, generation: u8) -> u64 {
+        let best_move = self.best_move.map_or(0, Move::to_bits) as u64;
+        let score = self.score as i16 as u16 as u64;
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        best_move
+            | score << 16
+            | (self.depth as u64) << 32
+            | bound << 40
+            | (generation as u64) << 48
+            | 1 << 63
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        Self {
+            // No move is ever packed as 0, since its origin and target would be the same.
+            best_move: Some(bits as u16)
+                .filter(|&bits| bits != 0)
+                .map(Move::from_bits),
+            score: (bits >> 16) as u16 as i16 as i32,
+            depth: (bits >> 32) as u8,
+            bound: match bits >> 40 & 0b11 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+        }
+    }
+}
+
+/// Returns the generation an entry packed with `Entry::to_bits` was stored in.
+fn generation_of(bits: u64) -> u8 {
+    (bits >> 48) as u8
+}
+
+#[derive(Default)]
+struct Slot {
+    /// The key XOR-ed with `data`.
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+        (self.check.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    fn store(&self, key: u64, data: u64) {
+        self.check.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.store(0, 0);
+    }
+}
+
+/// A depth-preferred slot followed by an always-replace slot.
+#[derive(Default)]
+struct Bucket {
+    slots: [Slot; 2],
+}
+
+/// A fixed-size hash table of search results.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    /// Incremented at the start of every search to tell old entries apart.
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    /// Returns an empty table taking at most `size_mb` megabytes.
+    pub fn new(size_mb: usize) -> Self {
+        let mut table = Self {
+            buckets: Vec::new(),
+            generation: AtomicU8::new(0),
+        };
+        table.resize(size_mb);
+        table
+    }
+
+    /// Replaces the table with an empty one taking at most `size_mb` megabytes.
+    ///
+    /// The number of buckets is rounded down to a power of two, with at least one bucket.
+    pub fn resize(&mut self, size_mb: usize) {
+        let buckets = (size_mb * 1024 * 1024 / std::mem::size_of::<Bucket>()).max(1);
+        let buckets = 1 << buckets.ilog2();
+        self.buckets = std::iter::repeat_with(Bucket::default)
+            .take(buckets)
+            .collect();
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Returns the size of the table in bytes.
+    pub fn size(&self) -> usize {
+        self.buckets.len() * std::mem::size_of::<Bucket>()
+    }
+
+    /// Removes every entry.
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            bucket.slots.iter().for_each(Slot::clear);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the start of a new search, making the entries stored until now the first to
+    /// be replaced.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn bucket(&self, key: u64) -> &Bucket {
+        // The number of buckets is a power of two.
+        &self.buckets[key as usize & (self.buckets.len() - 1)]
+    }
+
+    /// Returns the entry stored for the position with hash `key`, if any.
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        self.bucket(key).slots.iter().find_map(|slot| {
+            let (slot_key, data) = slot.load();
+            (slot_key == key && data != 0).then(|| Entry::from_bits(data))
+        })
+    }
+
+    /// Stores `entry` for the position with hash `key`.
+    ///
+    /// When the entry has no best move, the one already stored for the position is kept.
+    pub fn store(&self, key: u64, mut entry: Entry) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let [deepest, latest] = &self.bucket(key).slots;
+        let (deepest_key, deepest_data) = deepest.load();
+        let (latest_key, latest_data) = latest.load();
+
+        let replace_deepest = if deepest_key == key && deepest_data != 0 {
+            entry.best_move = entry.best_move.or(Entry::from_bits(deepest_data).best_move);
+            true
+        } else {
+            if latest_key == key && latest_data != 0 {
+                entry.best_move = entry.best_move.or(Entry::from_bits(latest_data).best_move);
+                // The entry is about to move to one slot or the other.
+                latest.clear();
+            }
+            deepest_data == 0
+                || generation_of(deepest_data) != generation
+                || entry.depth >= Entry::from_bits(deepest_data).depth
+        };
+        let slot = if replace_deepest { deepest } else { latest };
+        slot.store(key, entry.to_bits(generation));
+    }
+
+    /// Returns how full the table is, in permille, counting only the entries stored during
+    /// the current search.
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = &self.buckets[..self.buckets.len().min(500)];
+        let used = sample
+            .iter()
+            .flat_map(|bucket| &bucket.slots)
+            .filter(|slot| {
+                let (_, data) = slot.load();
+                data != 0 && generation_of(data) == generation
+            })
+            .count();
+        used * 1000 / (sample.len() * 2)
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIZE_MB)
+    }
+}
+
+impl std::fmt::Debug for TranspositionTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TranspositionTable")
+            .field("size", &self.size())
+            .field("hashfull", &self.hashfull())
+            .finish()
+    }
+}
+
+#[test]
+fn store_and_probe() {
+    let table = TranspositionTable::new(1);
+    assert_eq!(table.size(), 1024 * 1024);
+    let mov = Move::from_notation("e2e4").unwrap();
+    let entry = |depth, score| Entry {
+        best_move: Some(mov),
+        score,
+        depth,
+        bound: Bound::Lower,
+    };
+
+    assert_eq!(table.probe(42), None);
+    table.store(42, entry(3, -31_990));
+    assert_eq!(table.probe(42), Some(entry(3, -31_990)));
+    // A shallower entry for another position goes to the always-replace slot
+    let other = 42 + table.buckets.len() as u64;
+    table.store(other, entry(1, 15));
+    assert_eq!(table.probe(42), Some(entry(3, -31_990)));
+    assert_eq!(table.probe(other), Some(entry(1, 15)));
+    // Storing without a best move keeps the previous one
+    table.store(
+        42,
+        Entry {
+            best_move: None,
+            ..entry(4, 7)
+        },
+    );
+    assert_eq!(table.probe(42), Some(entry(4, 7)));
+
+    // Entries from previous searches are replaced first
+    table.new_search();
+    assert_eq!(table.hashfull(), 0);
+    let third = 42 + 2 * table.buckets.len() as u64;
+    table.store(third, entry(0, 0));
+    assert_eq!(table.probe(third), Some(entry(0, 0)));
+    assert_eq!(table.probe(42), None);
+    assert_eq!(table.probe(other), Some(entry(1, 15)));
+
+    table.clear();
+    assert_eq!(table.probe(third), None);
+}
+
+#[test]
+fn hashfull() {
+    let mut table = TranspositionTable::new(1);
+    table.resize(2);
+    assert_eq!(table.size(), 2 * 1024 * 1024);
+    let entry = Entry {
+        best_move: None,
+        score: 0,
+        depth: 1,
+        bound: Bound::Exact,
+    };
+    for key in 0..250 {
+        table.store(key, entry);
+    }
+    assert_eq!(table.hashfull(), 250);
+}
@@ -0,0 +1,218 @@
+//! Zobrist hashing.
+//!
+//! A position is hashed by XOR-ing together a random key for every piece on its square, the
+//! side to move, each castling right and the file of the en passant square. Playing a move
+//! only toggles the keys of what it changes, so `Board::make_move` keeps the hash up to date
+//! without recomputing it.
+//!
+//! The en passant file is only hashed when a pawn of the side to move stands next to the
+//! pawn that just moved, so that positions that only differ by an impossible en passant
+//! capture hash the same.
+
+use crate::attacks::pawn_attacks;
+use crate::bitboard::{Board, CastleRights, Square};
+use crate::bits;
+use crate::piece::{Color, Piece, PieceType};
+
+struct Keys {
+    pieces: [[u64; 64]; 12],
+    black_to_move: u64,
+    castling_rights: [u64; 4],
+    en_passant_files: [u64; 8],
+}
+
+/// Returns the next number of the splitmix64 generator, whose state is `state`.
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The keys, drawn at compile time from a fixed seed so the hashes are the same between
+/// builds.
+const KEYS: Keys = {
+    let mut state = 0x2b5c_7a0f_1e9d_4c63;
+    let mut pieces = [[0; 64]; 12];
+    let mut piece = 0;
+    while piece < 12 {
+        let mut square = 0;
+        while square < 64 {
+            pieces[piece][square] = splitmix64(&mut state);
+            square += 1;
+        }
+        piece += 1;
+    }
+    let black_to_move = splitmix64(&mut state);
+    let mut castling_rights = [0; 4];
+    let mut right = 0;
+    while right < 4 {
+        castling_rights[right] = splitmix64(&mut state);
+        right += 1;
+    }
+    let mut en_passant_files = [0; 8];
+    let mut file = 0;
+    while file < 8 {
+        en_passant_files[file] = splitmix64(&mut state);
+        file += 1;
+    }
+    Keys {
+        pieces,
+        black_to_move,
+        castling_rights,
+        en_passant_files,
+    }
+};
+
+#[inline(always)]
+fn keys() -> &'static Keys {
+    &KEYS
+}
+
+/// Hash of the starting position, for `Board::new` to build it in a constant expression.
+pub(crate) const START_HASH: u64 = {
+    // Pieces of the first rank from the a file to the h file.
+    let back_rank = [
+        PieceType::Rook as usize,
+        PieceType::Knight as usize,
+        PieceType::Bishop as usize,
+        PieceType::Queen as usize,
+        PieceType::King as usize,
+        PieceType::Bishop as usize,
+        PieceType::Knight as usize,
+        PieceType::Rook as usize,
+    ];
+    let keys = &KEYS.castling_rights;
+    let mut hash = keys[0] ^ keys[1] ^ keys[2] ^ keys[3];
+    let mut file = 0;
+    while file < 8 {
+        hash ^= KEYS.pieces[back_rank[file]][file] ^ KEYS.pieces[0][8 + file];
+        hash ^= KEYS.pieces[6 + back_rank[file]][56 + file] ^ KEYS.pieces[6][48 + file];
+        file += 1;
+    }
+    hash
+};
+
+/// Returns the key of `piece` standing on `square`.
+#[inline]
+pub fn piece_key(piece: Piece, square: Square) -> u64 {
+    let color = piece.color() as usize;
+    let piece_type = piece.piece_type() as usize;
+    keys().pieces[color * 6 + piece_type][square.index as usize]
+}
+
+/// Returns the key toggled when black is to move.
+#[inline]
+pub fn side_key() -> u64 {
+    keys().black_to_move
+}
+
+/// Returns the combined key of every right in `castling_rights`.
+#[inline]
+pub fn castling_key(castling_rights: CastleRights) -> u64 {
+    [
+        CastleRights::WhiteKS,
+        CastleRights::WhiteQS,
+        CastleRights::BlackKS,
+        CastleRights::BlackQS,
+    ]
+    .into_iter()
+    .zip(keys().castling_rights)
+    .filter(|&(right, _)| castling_rights.contains(right))
+    .fold(0, |key, (_, right_key)| key ^ right_key)
+}
+
+/// Returns the key of an en passant square on `file`.
+#[inline]
+pub fn en_passant_key(file: u32) -> u64 {
+    keys().en_passant_files[file as usize]
+}
+
+impl Board {
+    /// Returns the key of the en passant square if a pawn of the side to move can capture
+    /// on it, and 0 otherwise.
+    pub(crate) fn en_passant_hash(&self) -> u64 {
+        let color = self.color_to_move();
+        match self.en_passant_square() {
+            Some(square)
+                if pawn_attacks(square.index, !color) & self.pieces(Piece::Pawn(color)) != 0 =>
+            {
+                en_passant_key(square.get_file())
+            }
+            _ => 0,
+        }
+    }
+
+    /// Computes the Zobrist hash of the position from scratch.
+    ///
+    /// `Board::hash` returns the same value, maintained incrementally.
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = castling_key(self.castling_rights()) ^ self.en_passant_hash();
+        if self.color_to_move() == Color::Black {
+            hash ^= side_key();
+        }
+        for color in [Color::White, Color::Black] {
+            for piece_type in [
+                PieceType::Pawn,
+                PieceType::Knight,
+                PieceType::Bishop,
+                PieceType::Rook,
+                PieceType::Queen,
+                PieceType::King,
+            ] {
+                let piece = Piece::new(piece_type, color);
+                let mut pieces = self.pieces(piece);
+                while pieces != 0 {
+                    let index = bits::pop_msb(&mut pieces);
+                    hash ^= piece_key(piece, Square { index });
+                }
+            }
+        }
+        hash
+    }
+}
+
+#[test]
+fn incremental_hash() {
+    use crate::parser::load_position_from_fen;
+
+    // Transpositions hash the same
+    let mut a = Board::new();
+    let mut b = Board::new();
+    for text in ["g1f3", "g8f6", "b1c3"] {
+        a.make_move(a.move_from_notation(text).unwrap());
+    }
+    for text in ["b1c3", "g8f6", "g1f3"] {
+        b.make_move(b.move_from_notation(text).unwrap());
+    }
+    assert_eq!(a.hash(), b.hash());
+    assert_ne!(a.hash(), Board::new().hash());
+    assert_eq!(Board::new().hash(), Board::new().compute_hash());
+
+    // An en passant square without a pawn to capture doesn't change the hash
+    let with = load_position_from_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1").unwrap();
+    let without = load_position_from_fen("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1").unwrap();
+    assert_eq!(with.hash(), without.hash());
+    let with = load_position_from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+    let without = load_position_from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1").unwrap();
+    assert_ne!(with.hash(), without.hash());
+
+    // Every kind of move keeps the hash in sync, and taking it back restores it
+    let mut board = load_position_from_fen(
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    )
+    .unwrap();
+    for mov in board.clone().get_legal_moves() {
+        let hash = board.hash();
+        let undo = board.make_move(mov);
+        assert_eq!(board.hash(), board.compute_hash(), "{}", mov);
+        for reply in board.clone().get_legal_moves() {
+            let undo = board.make_move(reply);
+            assert_eq!(board.hash(), board.compute_hash(), "{} {}", mov, reply);
+            board.undo_move(reply, undo);
+        }
+        board.undo_move(mov, undo);
+        assert_eq!(board.hash(), hash);
+    }
+}